@@ -0,0 +1,57 @@
+#[cfg(test)]
+pub(crate) mod fault;
+mod local;
+mod memory;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::Bytes;
+pub use local::{FileMode, LocalBackend};
+pub use memory::MemoryBackend;
+
+/// A file that can be read at arbitrary offsets. Files are immutable once they are opened for
+/// reading.
+pub trait RandomAccessFile: Send + Sync {
+    /// Read exactly `len` bytes at `offset`.
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes>;
+
+    /// Get the size of the file.
+    fn size(&self) -> u64;
+}
+
+/// A file that is being written sequentially.
+pub trait WritableFile: Send {
+    /// Append `data` to the end of the file.
+    fn append(&mut self, data: &[u8]) -> Result<()>;
+
+    /// Make everything appended so far durable.
+    fn sync(&mut self) -> Result<()>;
+}
+
+/// The environment the engine stores its files in. All file system access of the engine goes
+/// through this trait, so that it can run on the local disk, in memory, or on anything else that
+/// provides the same primitives.
+pub trait StorageBackend: Send + Sync {
+    /// Create a new file for writing, replacing any existing file at `path`.
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>>;
+
+    /// Open an existing file for reading.
+    fn open(&self, path: &Path) -> Result<Arc<dyn RandomAccessFile>>;
+
+    /// Delete a file.
+    fn delete(&self, path: &Path) -> Result<()>;
+
+    /// Atomically rename a file, replacing any existing file at `to`.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// List the files directly inside `dir`, or none if it does not exist.
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>>;
+
+    /// Make the creation and deletion of files inside `dir` durable.
+    fn sync_dir(&self, dir: &Path) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::{RandomAccessFile, StorageBackend, WritableFile};
+
+#[derive(Clone, Default)]
+struct FaultFile {
+    data: Vec<u8>,
+    /// Length of the prefix of `data` that survives a crash.
+    synced_len: usize,
+}
+
+struct FaultState {
+    /// Files as the running process sees them.
+    files: BTreeMap<PathBuf, FaultFile>,
+    /// Files as the directory entries on disk see them, updated by `sync_dir`.
+    durable_files: BTreeSet<PathBuf>,
+    /// Files that are deleted but whose deletion is not durable yet.
+    unlinked: BTreeMap<PathBuf, FaultFile>,
+    /// Keep a random part of unsynced data on crash instead of dropping all of it.
+    torn_writes: bool,
+    fail_sync: bool,
+    fail_read: bool,
+    /// Number of renames that may still succeed before every rename fails.
+    renames_until_failure: Option<usize>,
+    /// Number of mutating operations that may still succeed before the process is killed.
+    ops_until_kill: Option<usize>,
+    killed: bool,
+    rng: StdRng,
+}
+
+impl FaultState {
+    /// Account for one mutating operation, failing it if the process has been killed.
+    fn check_alive(&mut self) -> Result<()> {
+        if let Some(ops) = self.ops_until_kill.as_mut() {
+            if *ops == 0 {
+                self.killed = true;
+            } else {
+                *ops -= 1;
+            }
+        }
+        if self.killed {
+            bail!("injected fault: process killed");
+        }
+        Ok(())
+    }
+}
+
+/// A test-only backend that keeps files in memory and simulates what a crash does to them.
+///
+/// Appended data is lost on [`crash`](Self::crash) unless the file was synced, and newly created
+/// or deleted files only survive a crash once their directory was synced. Syncs, reads and renames
+/// can be made to fail, and the whole "process" can be killed after a number of operations, after which
+/// every operation fails until the next crash.
+#[derive(Clone)]
+pub struct FaultInjectionBackend {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl FaultInjectionBackend {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FaultState {
+                files: BTreeMap::new(),
+                durable_files: BTreeSet::new(),
+                unlinked: BTreeMap::new(),
+                torn_writes: false,
+                fail_sync: false,
+                fail_read: false,
+                renames_until_failure: None,
+                ops_until_kill: None,
+                killed: false,
+                rng: StdRng::seed_from_u64(seed),
+            })),
+        }
+    }
+
+    /// Keep a random prefix of unsynced data on crash, instead of dropping it.
+    pub fn set_torn_writes(&self, torn_writes: bool) {
+        self.state.lock().torn_writes = torn_writes;
+    }
+
+    /// Make every `sync` and `sync_dir` fail.
+    pub fn set_fail_sync(&self, fail_sync: bool) {
+        self.state.lock().fail_sync = fail_sync;
+    }
+
+    /// Make every read fail.
+    pub fn set_fail_read(&self, fail_read: bool) {
+        self.state.lock().fail_read = fail_read;
+    }
+
+    /// Make every rename fail once `renames` more succeed, or stop failing them with `None`.
+    /// Unlike a kill, the other operations keep working.
+    pub fn set_fail_rename_after(&self, renames: Option<usize>) {
+        self.state.lock().renames_until_failure = renames;
+    }
+
+    /// Kill the process after `ops` more mutating operations succeed.
+    pub fn kill_after(&self, ops: usize) {
+        self.state.lock().ops_until_kill = Some(ops);
+    }
+
+    /// Check whether the process has been killed.
+    pub fn is_killed(&self) -> bool {
+        self.state.lock().killed
+    }
+
+    /// Simulate a power loss: drop everything that is not durable and bring the process back.
+    pub fn crash(&self) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        let mut files = BTreeMap::new();
+        for path in &state.durable_files {
+            let Some(mut file) = state
+                .files
+                .remove(path)
+                .or_else(|| state.unlinked.remove(path))
+            else {
+                continue;
+            };
+            let mut len = file.synced_len;
+            if state.torn_writes && file.data.len() > len {
+                len = state.rng.gen_range(len..=file.data.len());
+            }
+            file.data.truncate(len);
+            file.synced_len = len;
+            files.insert(path.clone(), file);
+        }
+        state.files = files;
+        state.unlinked.clear();
+        state.ops_until_kill = None;
+        state.killed = false;
+    }
+}
+
+struct FaultReadFile {
+    state: Arc<Mutex<FaultState>>,
+    data: Bytes,
+}
+
+impl RandomAccessFile for FaultReadFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        if self.state.lock().fail_read {
+            bail!("injected fault: read failed");
+        }
+        if offset + len > self.data.len() as u64 {
+            bail!(
+                "read out of bounds: offset={}, len={}, size={}",
+                offset,
+                len,
+                self.data.len()
+            );
+        }
+        Ok(self.data.slice(offset as usize..(offset + len) as usize))
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+struct FaultWritableFile {
+    state: Arc<Mutex<FaultState>>,
+    path: PathBuf,
+}
+
+impl FaultWritableFile {
+    fn with_file<T>(&self, f: impl FnOnce(&mut FaultFile) -> T) -> Result<T> {
+        let mut state = self.state.lock();
+        state.check_alive()?;
+        let file = state
+            .files
+            .get_mut(&self.path)
+            .ok_or_else(|| anyhow!("file not found: {}", self.path.display()))?;
+        Ok(f(file))
+    }
+}
+
+impl WritableFile for FaultWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.with_file(|file| file.data.extend_from_slice(data))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if self.state.lock().fail_sync {
+            bail!("injected fault: fsync failed");
+        }
+        self.with_file(|file| file.synced_len = file.data.len())
+    }
+}
+
+impl StorageBackend for FaultInjectionBackend {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let mut state = self.state.lock();
+        state.check_alive()?;
+        state.files.insert(path.to_path_buf(), FaultFile::default());
+        Ok(Box::new(FaultWritableFile {
+            state: self.state.clone(),
+            path: path.to_path_buf(),
+        }))
+    }
+
+    fn open(&self, path: &Path) -> Result<Arc<dyn RandomAccessFile>> {
+        let state = self.state.lock();
+        if state.fail_read {
+            bail!("injected fault: open failed");
+        }
+        let file = state
+            .files
+            .get(path)
+            .ok_or_else(|| anyhow!("file not found: {}", path.display()))?;
+        Ok(Arc::new(FaultReadFile {
+            state: self.state.clone(),
+            data: Bytes::copy_from_slice(&file.data),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_alive()?;
+        let file = state
+            .files
+            .remove(path)
+            .ok_or_else(|| anyhow!("file not found: {}", path.display()))?;
+        state.unlinked.insert(path.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        state.check_alive()?;
+        match state.renames_until_failure.as_mut() {
+            Some(0) => bail!("injected fault: rename failed"),
+            Some(renames) => *renames -= 1,
+            None => {}
+        }
+        let file = state
+            .files
+            .remove(from)
+            .ok_or_else(|| anyhow!("file not found: {}", from.display()))?;
+        // Until the directory is synced, a crash brings back both the old name and whatever the
+        // new name pointed to before.
+        state.unlinked.insert(from.to_path_buf(), file.clone());
+        if let Some(replaced) = state.files.insert(to.to_path_buf(), file) {
+            state.unlinked.insert(to.to_path_buf(), replaced);
+        }
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .state
+            .lock()
+            .files
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        let mut state = self.state.lock();
+        if state.fail_sync {
+            bail!("injected fault: fsync failed");
+        }
+        state.check_alive()?;
+        let state = &mut *state;
+        state
+            .durable_files
+            .retain(|path| path.parent() != Some(dir));
+        state.durable_files.extend(
+            state
+                .files
+                .keys()
+                .filter(|path| path.parent() == Some(dir))
+                .cloned(),
+        );
+        state.unlinked.retain(|path, _| path.parent() != Some(dir));
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+
+use super::{RandomAccessFile, StorageBackend, WritableFile};
+
+/// How a local file is read back after it is opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileMode {
+    /// Every read is a `pread` into a freshly allocated buffer.
+    #[default]
+    Read,
+    /// The whole file is memory-mapped, and reads slice directly into the mapping.
+    Mmap,
+}
+
+/// Stores files on the local file system.
+#[derive(Clone, Debug, Default)]
+pub struct LocalBackend {
+    file_mode: FileMode,
+}
+
+impl LocalBackend {
+    pub fn new(file_mode: FileMode) -> Self {
+        Self { file_mode }
+    }
+}
+
+enum FileHandle {
+    File(File),
+    /// The mapping is owned by the `Bytes`, so slices of it stay valid after the file is closed.
+    Mmap(Bytes),
+}
+
+struct LocalFile(FileHandle, u64);
+
+impl RandomAccessFile for LocalFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        if offset + len > self.1 {
+            bail!(
+                "read out of bounds: offset={}, len={}, size={}",
+                offset,
+                len,
+                self.1
+            );
+        }
+        match &self.0 {
+            FileHandle::File(file) => {
+                use std::os::unix::fs::FileExt;
+                let mut data = vec![0; len as usize];
+                file.read_exact_at(&mut data[..], offset)?;
+                Ok(data.into())
+            }
+            FileHandle::Mmap(map) => Ok(map.slice(offset as usize..(offset + len) as usize)),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.1
+    }
+}
+
+struct LocalWritableFile(File);
+
+impl WritableFile for LocalWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.0.write_all(data)?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.0.sync_all()?;
+        Ok(())
+    }
+}
+
+impl StorageBackend for LocalBackend {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let f = File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Box::new(LocalWritableFile(f)))
+    }
+
+    fn open(&self, path: &Path) -> Result<Arc<dyn RandomAccessFile>> {
+        let f = File::options().read(true).write(false).open(path)?;
+        let len = f.metadata()?.len();
+        let handle = match self.file_mode {
+            FileMode::Read => FileHandle::File(f),
+            FileMode::Mmap => {
+                // SAFETY: SST files are immutable once written, and the engine never truncates or
+                // rewrites a file that is still referenced by an `SsTable`.
+                let map = unsafe { memmap2::Mmap::map(&f)? };
+                FileHandle::Mmap(Bytes::from_owner(map))
+            }
+        };
+        Ok(Arc::new(LocalFile(handle, len)))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        std::fs::rename(from, to)?;
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(paths),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+}
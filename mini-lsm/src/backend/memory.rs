@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use super::{RandomAccessFile, StorageBackend, WritableFile};
+
+type FileData = Arc<Mutex<Vec<u8>>>;
+
+/// Keeps all files in memory. Nothing is ever persisted, which makes it useful for tests.
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    files: Arc<Mutex<BTreeMap<PathBuf, FileData>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+struct MemoryFile(Bytes);
+
+impl RandomAccessFile for MemoryFile {
+    fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        if offset + len > self.0.len() as u64 {
+            bail!(
+                "read out of bounds: offset={}, len={}, size={}",
+                offset,
+                len,
+                self.0.len()
+            );
+        }
+        Ok(self.0.slice(offset as usize..(offset + len) as usize))
+    }
+
+    fn size(&self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+struct MemoryWritableFile(FileData);
+
+impl WritableFile for MemoryWritableFile {
+    fn append(&mut self, data: &[u8]) -> Result<()> {
+        self.0.lock().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn create(&self, path: &Path) -> Result<Box<dyn WritableFile>> {
+        let data = FileData::default();
+        self.files.lock().insert(path.to_path_buf(), data.clone());
+        Ok(Box::new(MemoryWritableFile(data)))
+    }
+
+    fn open(&self, path: &Path) -> Result<Arc<dyn RandomAccessFile>> {
+        let data = self
+            .files
+            .lock()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow!("file not found: {}", path.display()))?;
+        // Files are immutable once opened for reading, so take a snapshot of the content.
+        let data = Bytes::copy_from_slice(&data.lock());
+        Ok(Arc::new(MemoryFile(data)))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        self.files
+            .lock()
+            .remove(path)
+            .ok_or_else(|| anyhow!("file not found: {}", path.display()))?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        let mut files = self.files.lock();
+        let data = files
+            .remove(from)
+            .ok_or_else(|| anyhow!("file not found: {}", from.display()))?;
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .lock()
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn sync_dir(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+}
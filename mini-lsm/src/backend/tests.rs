@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use super::*;
+
+fn check_backend(backend: &dyn StorageBackend, dir: &Path) {
+    let path = dir.join("1.sst");
+    let mut file = backend.create(&path).unwrap();
+    file.append(b"hello, ").unwrap();
+    file.append(b"world").unwrap();
+    file.sync().unwrap();
+    drop(file);
+    backend.sync_dir(dir).unwrap();
+
+    let file = backend.open(&path).unwrap();
+    assert_eq!(file.size(), 12);
+    assert_eq!(&file.read_at(0, 5).unwrap()[..], b"hello");
+    assert_eq!(&file.read_at(7, 5).unwrap()[..], b"world");
+    assert!(file.read_at(7, 6).is_err());
+
+    backend.create(&dir.join("2.sst")).unwrap();
+    assert_eq!(
+        backend.list(dir).unwrap(),
+        vec![dir.join("1.sst"), dir.join("2.sst")]
+    );
+    backend.delete(&path).unwrap();
+    assert_eq!(backend.list(dir).unwrap(), vec![dir.join("2.sst")]);
+    assert!(backend.open(&path).is_err());
+    assert!(backend.list(&dir.join("missing")).unwrap().is_empty());
+}
+
+#[test]
+fn test_local_backend() {
+    let dir = tempdir().unwrap();
+    check_backend(&LocalBackend::new(FileMode::Read), dir.path());
+}
+
+#[test]
+fn test_local_backend_mmap() {
+    let dir = tempdir().unwrap();
+    check_backend(&LocalBackend::new(FileMode::Mmap), dir.path());
+}
+
+#[test]
+fn test_memory_backend() {
+    check_backend(&MemoryBackend::new(), Path::new("/lsm"));
+}
+
+fn check_rename(backend: &dyn StorageBackend, dir: &Path) {
+    let mut file = backend.create(&dir.join("1.sst.tmp")).unwrap();
+    file.append(b"new").unwrap();
+    drop(file);
+    let mut file = backend.create(&dir.join("1.sst")).unwrap();
+    file.append(b"old").unwrap();
+    drop(file);
+    backend
+        .rename(&dir.join("1.sst.tmp"), &dir.join("1.sst"))
+        .unwrap();
+    assert_eq!(backend.list(dir).unwrap(), vec![dir.join("1.sst")]);
+    let file = backend.open(&dir.join("1.sst")).unwrap();
+    assert_eq!(&file.read_at(0, 3).unwrap()[..], b"new");
+}
+
+#[test]
+fn test_local_backend_rename() {
+    let dir = tempdir().unwrap();
+    check_rename(&LocalBackend::default(), dir.path());
+}
+
+#[test]
+fn test_memory_backend_rename() {
+    check_rename(&MemoryBackend::new(), Path::new("/lsm"));
+}
@@ -0,0 +1,156 @@
+//! Key-value separation in the style of WiscKey: large values are appended to blob files, and the
+//! SSTs only store pointers to them, so compaction does not have to rewrite the values.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::backend::StorageBackend;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::table::{FileObject, FileWriter};
+
+/// The location of a value in a blob file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobPointer {
+    /// ID of the blob file.
+    pub file_id: usize,
+    /// Offset of the value in the blob file.
+    pub offset: u64,
+    /// Length of the value.
+    pub len: u32,
+}
+
+impl BlobPointer {
+    pub const ENCODED_LEN: usize = 20;
+
+    /// Encode the pointer to be stored in place of the value.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::ENCODED_LEN);
+        buf.put_u64(self.file_id as u64);
+        buf.put_u64(self.offset);
+        buf.put_u32(self.len);
+        buf
+    }
+
+    /// Decode a pointer from an SST value.
+    pub fn decode(mut buf: &[u8]) -> Result<Self> {
+        if buf.len() != Self::ENCODED_LEN {
+            bail!("invalid blob pointer length: {}", buf.len());
+        }
+        Ok(Self {
+            file_id: buf.get_u64() as usize,
+            offset: buf.get_u64(),
+            len: buf.get_u32(),
+        })
+    }
+}
+
+/// Writes a new blob file.
+///
+/// A blob file is a sequence of records, each of which is `key_len (u16) | key | value_len (u32) |
+/// value`. The key is kept next to the value so that garbage collection can tell whether the value
+/// is still live.
+pub struct BlobFileBuilder {
+    writer: FileWriter,
+    file_id: usize,
+    offset: u64,
+}
+
+impl BlobFileBuilder {
+    /// Start writing the blob file `file_id` at `path`. Nothing is visible at `path` until
+    /// [`finish`](Self::finish).
+    pub fn create(backend: Arc<dyn StorageBackend>, file_id: usize, path: &Path) -> Result<Self> {
+        Ok(Self {
+            writer: FileWriter::create(backend, path)?,
+            file_id,
+            offset: 0,
+        })
+    }
+
+    /// Throttle the writes of the blob file with the given rate limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>, priority: IoPriority) {
+        self.writer.set_rate_limiter(rate_limiter, priority);
+    }
+
+    /// Append a value and return the pointer to it.
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> Result<BlobPointer> {
+        let mut record = Vec::with_capacity(2 + key.len() + 4 + value.len());
+        record.put_u16(key.len() as u16);
+        record.put_slice(key);
+        record.put_u32(value.len() as u32);
+        let pointer = BlobPointer {
+            file_id: self.file_id,
+            offset: self.offset + record.len() as u64,
+            len: value.len() as u32,
+        };
+        record.put_slice(value);
+        self.writer.append(&record)?;
+        self.offset += record.len() as u64;
+        Ok(pointer)
+    }
+
+    /// Make the blob file durable and open it for reading.
+    pub fn finish(self) -> Result<FileObject> {
+        self.writer.finish()
+    }
+}
+
+/// A record of a blob file.
+pub struct BlobRecord {
+    pub key: Bytes,
+    pub pointer: BlobPointer,
+    pub value: Bytes,
+}
+
+/// Read all records of a blob file.
+pub fn read_blob_records(file_id: usize, file: &FileObject) -> Result<Vec<BlobRecord>> {
+    let data = file.read(0, file.size())?;
+    let mut buf = &data[..];
+    let mut records = Vec::new();
+    while buf.has_remaining() {
+        if buf.remaining() < 2 {
+            bail!("truncated record in blob file {}", file_id);
+        }
+        let key_len = buf.get_u16() as usize;
+        if buf.remaining() < key_len + 4 {
+            bail!("truncated record in blob file {}", file_id);
+        }
+        let key = data.slice_ref(&buf[..key_len]);
+        buf.advance(key_len);
+        let value_len = buf.get_u32() as usize;
+        if buf.remaining() < value_len {
+            bail!("truncated record in blob file {}", file_id);
+        }
+        let offset = (data.len() - buf.remaining()) as u64;
+        let value = data.slice_ref(&buf[..value_len]);
+        buf.advance(value_len);
+        records.push(BlobRecord {
+            key,
+            pointer: BlobPointer {
+                file_id,
+                offset,
+                len: value_len as u32,
+            },
+            value,
+        });
+    }
+    Ok(records)
+}
+
+/// The live blob files of a snapshot, by ID.
+pub type BlobFiles = BTreeMap<usize, Arc<FileObject>>;
+
+/// Read the value an encoded [`BlobPointer`] points to.
+pub fn read_blob(files: &BlobFiles, pointer: &[u8]) -> Result<Bytes> {
+    let pointer = BlobPointer::decode(pointer)?;
+    let file = files
+        .get(&pointer.file_id)
+        .ok_or_else(|| anyhow!("blob file {} not found", pointer.file_id))?;
+    file.read(pointer.offset, pointer.len as u64)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,57 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use super::*;
+use crate::backend::MemoryBackend;
+
+#[test]
+fn test_blob_pointer_encode_decode() {
+    let pointer = BlobPointer {
+        file_id: 3,
+        offset: 1 << 40,
+        len: 12345,
+    };
+    let encoded = pointer.encode();
+    assert_eq!(encoded.len(), BlobPointer::ENCODED_LEN);
+    assert_eq!(BlobPointer::decode(&encoded).unwrap(), pointer);
+    assert!(BlobPointer::decode(&encoded[1..]).is_err());
+}
+
+#[test]
+fn test_blob_file_read_write() {
+    let backend = Arc::new(MemoryBackend::new());
+    let mut builder = BlobFileBuilder::create(backend, 7, Path::new("/00007.blob")).unwrap();
+    let mut pointers = Vec::new();
+    for idx in 0..10 {
+        let key = format!("key_{:03}", idx);
+        let value = format!("value_{}", idx).repeat(idx + 1);
+        pointers.push(builder.add(key.as_bytes(), value.as_bytes()).unwrap());
+    }
+    let file = builder.finish().unwrap();
+
+    let mut files = BlobFiles::new();
+    files.insert(7, Arc::new(file));
+    for (idx, pointer) in pointers.iter().enumerate() {
+        assert_eq!(pointer.file_id, 7);
+        let value = read_blob(&files, &pointer.encode()).unwrap();
+        assert_eq!(value, format!("value_{}", idx).repeat(idx + 1).as_bytes());
+    }
+
+    let records = read_blob_records(7, &files[&7]).unwrap();
+    assert_eq!(records.len(), 10);
+    for (idx, record) in records.iter().enumerate() {
+        assert_eq!(record.key, format!("key_{:03}", idx).as_bytes());
+        assert_eq!(record.pointer, pointers[idx]);
+        assert_eq!(
+            record.value,
+            format!("value_{}", idx).repeat(idx + 1).as_bytes()
+        );
+    }
+
+    let missing = BlobPointer {
+        file_id: 8,
+        offset: 0,
+        len: 1,
+    };
+    assert!(read_blob(&files, &missing.encode()).is_err());
+}
@@ -7,16 +7,50 @@ pub use iterator::BlockIterator;
 
 pub const SIZEOF_U16: usize = std::mem::size_of::<u16>();
 
+/// Longest key a block entry can hold, as its length is stored as a `u16`.
+pub const MAX_KEY_LEN: usize = u16::MAX as usize;
+
+/// Longest value a block entry can hold inline, as its length is stored as a `u16`. Longer values
+/// have to be separated into blob files.
+pub const MAX_VALUE_LEN: usize = u16::MAX as usize;
+
+/// Set in the flags of an entry whose value is a pointer into a blob file rather than the value
+/// itself.
+pub(crate) const BLOB_POINTER_FLAG: u8 = 1 << 0;
+
+/// Set in the flags of an entry that expires. The expiry time follows the value as a `u64`.
+pub(crate) const EXPIRES_FLAG: u8 = 1 << 1;
+
+/// Attributes of an entry besides its key and value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EntryMeta {
+    /// The value is a pointer into a blob file rather than the value itself.
+    pub is_blob_pointer: bool,
+    /// When the entry expires, in milliseconds since the Unix epoch.
+    pub expires_at: Option<u64>,
+}
+
+impl EntryMeta {
+    /// Check if the entry has expired at `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+    }
+}
+
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
 pub struct Block {
-    data: Vec<u8>,
+    /// The data section. When decoded from disk, this is a slice of the buffer returned by the
+    /// file read, so iterating the block never copies the key-value pairs.
+    data: Bytes,
     offsets: Vec<u16>,
 }
 
 impl Block {
     pub fn encode(&self) -> Bytes {
-        let mut buf = self.data.clone();
+        let mut buf =
+            Vec::with_capacity(self.data.len() + self.offsets.len() * SIZEOF_U16 + SIZEOF_U16);
+        buf.put_slice(&self.data);
         let offsets_len = self.offsets.len();
         for offset in &self.offsets {
             buf.put_u16(*offset);
@@ -25,7 +59,7 @@ impl Block {
         buf.into()
     }
 
-    pub fn decode(data: &[u8]) -> Self {
+    pub fn decode(data: Bytes) -> Self {
         let entry_offsets_len = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
         let data_end = data.len() - SIZEOF_U16 - entry_offsets_len * SIZEOF_U16;
         let offsets_raw = &data[data_end..data.len() - SIZEOF_U16];
@@ -33,7 +67,7 @@ impl Block {
             .chunks(SIZEOF_U16)
             .map(|mut x| x.get_u16())
             .collect();
-        let data = data[0..data_end].to_vec();
+        let data = data.slice(0..data_end);
         Self { data, offsets }
     }
 }
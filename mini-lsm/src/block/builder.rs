@@ -1,6 +1,8 @@
 use bytes::BufMut;
 
-use super::{Block, SIZEOF_U16};
+use super::{
+    Block, EntryMeta, BLOB_POINTER_FLAG, EXPIRES_FLAG, MAX_KEY_LEN, MAX_VALUE_LEN, SIZEOF_U16,
+};
 
 /// Builds a block.
 pub struct BlockBuilder {
@@ -29,8 +31,29 @@ impl BlockBuilder {
     /// Adds a key-value pair to the block. Returns false when the block is full.
     #[must_use]
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> bool {
+        self.add_entry(key, value, EntryMeta::default())
+    }
+
+    /// Adds a key-value pair with the given attributes to the block. Returns false when the block
+    /// is full.
+    #[must_use]
+    pub fn add_entry(&mut self, key: &[u8], value: &[u8], meta: EntryMeta) -> bool {
         assert!(!key.is_empty(), "key must not be empty");
-        if self.estimated_size() + key.len() + value.len() + SIZEOF_U16 * 3 > self.block_size
+        assert!(
+            key.len() <= MAX_KEY_LEN,
+            "key too large: {} bytes",
+            key.len()
+        );
+        assert!(
+            value.len() <= MAX_VALUE_LEN,
+            "value too large to store inline: {} bytes",
+            value.len()
+        );
+        let expires_len = meta.expires_at.map_or(0, |_| std::mem::size_of::<u64>());
+        // Key length, flags, value length and offset.
+        let overhead = SIZEOF_U16 * 3 + 1;
+        if self.estimated_size() + key.len() + value.len() + expires_len + overhead
+            > self.block_size
             && !self.is_empty()
         {
             return false;
@@ -38,8 +61,19 @@ impl BlockBuilder {
         self.offsets.push(self.data.len() as u16);
         self.data.put_u16(key.len() as u16);
         self.data.put(key);
+        let mut flags = 0;
+        if meta.is_blob_pointer {
+            flags |= BLOB_POINTER_FLAG;
+        }
+        if meta.expires_at.is_some() {
+            flags |= EXPIRES_FLAG;
+        }
+        self.data.put_u8(flags);
         self.data.put_u16(value.len() as u16);
         self.data.put(value);
+        if let Some(expires_at) = meta.expires_at {
+            self.data.put_u64(expires_at);
+        }
         true
     }
 
@@ -54,7 +88,7 @@ impl BlockBuilder {
             panic!("block should not be empty");
         }
         Block {
-            data: self.data,
+            data: self.data.into(),
             offsets: self.offsets,
         }
     }
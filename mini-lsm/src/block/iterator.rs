@@ -1,14 +1,19 @@
+use std::ops::Range;
 use std::sync::Arc;
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
 
-use super::Block;
+use super::{Block, EntryMeta, BLOB_POINTER_FLAG, EXPIRES_FLAG, SIZEOF_U16};
 
 /// Iterates on a block.
 pub struct BlockIterator {
     block: Arc<Block>,
-    key: Vec<u8>,
-    value: Vec<u8>,
+    /// Range of the current key in the block data.
+    key: Range<usize>,
+    /// Range of the current value in the block data.
+    value: Range<usize>,
+    /// Attributes of the current entry.
+    meta: EntryMeta,
     idx: usize,
 }
 
@@ -16,8 +21,9 @@ impl BlockIterator {
     fn new(block: Arc<Block>) -> Self {
         Self {
             block,
-            key: Vec::new(),
-            value: Vec::new(),
+            key: 0..0,
+            value: 0..0,
+            meta: EntryMeta::default(),
             idx: 0,
         }
     }
@@ -39,13 +45,31 @@ impl BlockIterator {
     /// Returns the key of the current entry.
     pub fn key(&self) -> &[u8] {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.key
+        &self.block.data[self.key.clone()]
     }
 
     /// Returns the value of the current entry.
     pub fn value(&self) -> &[u8] {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.value
+        &self.block.data[self.value.clone()]
+    }
+
+    /// Returns the key of the current entry as a `Bytes` sharing the block buffer.
+    pub fn key_bytes(&self) -> Bytes {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.block.data.slice(self.key.clone())
+    }
+
+    /// Returns the value of the current entry as a `Bytes` sharing the block buffer.
+    pub fn value_bytes(&self) -> Bytes {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.block.data.slice(self.value.clone())
+    }
+
+    /// Returns the attributes of the current entry.
+    pub fn meta(&self) -> EntryMeta {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.meta
     }
 
     /// Returns true if the iterator is valid.
@@ -61,8 +85,8 @@ impl BlockIterator {
     /// Seeks to the idx-th key in the block.
     fn seek_to(&mut self, idx: usize) {
         if idx >= self.block.offsets.len() {
-            self.key.clear();
-            self.value.clear();
+            self.key = 0..0;
+            self.value = 0..0;
             return;
         }
         let offset = self.block.offsets[idx] as usize;
@@ -76,18 +100,22 @@ impl BlockIterator {
         self.seek_to(self.idx);
     }
 
+    /// Records where the key and value of the entry at `offset` live in the block data, without
+    /// copying them.
     fn seek_to_offset(&mut self, offset: usize) {
         let mut entry = &self.block.data[offset..];
         let key_len = entry.get_u16() as usize;
-        let key = entry[..key_len].to_vec();
+        let key_begin = offset + SIZEOF_U16;
+        self.key = key_begin..key_begin + key_len;
         entry.advance(key_len);
-        self.key.clear();
-        self.key.extend(key);
+        let flags = entry.get_u8();
+        self.meta.is_blob_pointer = flags & BLOB_POINTER_FLAG != 0;
+        let has_expiry = flags & EXPIRES_FLAG != 0;
         let value_len = entry.get_u16() as usize;
-        let value = entry[..value_len].to_vec();
+        let value_begin = self.key.end + 1 + SIZEOF_U16;
+        self.value = value_begin..value_begin + value_len;
         entry.advance(value_len);
-        self.value.clear();
-        self.value.extend(value);
+        self.meta.expires_at = has_expiry.then(|| entry.get_u64());
     }
 
     /// Seek to the first key that >= `key`.
@@ -56,7 +56,7 @@ fn test_block_encode() {
 fn test_block_decode() {
     let block = generate_block();
     let encoded = block.encode();
-    let decoded_block = Block::decode(&encoded);
+    let decoded_block = Block::decode(encoded);
     assert_eq!(block.offsets, decoded_block.offsets);
     assert_eq!(block.data, decoded_block.data);
 }
@@ -120,3 +120,74 @@ fn test_block_seek_key() {
         iter.seek_to_key(b"k");
     }
 }
+
+#[test]
+fn test_block_iterator_zero_copy() {
+    let encoded = generate_block().encode();
+    let range = encoded.as_ptr_range();
+    let block = Arc::new(Block::decode(encoded));
+    let mut iter = BlockIterator::create_and_seek_to_first(block);
+    while iter.is_valid() {
+        assert!(range.contains(&iter.key().as_ptr()));
+        assert!(range.contains(&iter.value().as_ptr()));
+        assert_eq!(iter.value_bytes().as_ptr(), iter.value().as_ptr());
+        iter.next();
+    }
+}
+
+#[test]
+fn test_block_blob_pointer() {
+    let mut builder = BlockBuilder::new(10000);
+    assert!(builder.add(b"key_1", b"inline"));
+    let meta = EntryMeta {
+        is_blob_pointer: true,
+        expires_at: None,
+    };
+    assert!(builder.add_entry(b"key_2", b"pointer", meta));
+    let block = Arc::new(Block::decode(builder.build().encode()));
+    let mut iter = BlockIterator::create_and_seek_to_first(block);
+    assert_eq!(iter.value(), b"inline");
+    assert_eq!(iter.meta(), EntryMeta::default());
+    iter.next();
+    assert_eq!(iter.value(), b"pointer");
+    assert_eq!(iter.meta(), meta);
+}
+
+#[test]
+fn test_block_expiry() {
+    let mut builder = BlockBuilder::new(10000);
+    let expires = EntryMeta {
+        is_blob_pointer: false,
+        expires_at: Some(1000),
+    };
+    assert!(builder.add_entry(b"key_1", b"value_1", expires));
+    assert!(builder.add(b"key_2", b"value_2"));
+    let block = Arc::new(Block::decode(builder.build().encode()));
+    let mut iter = BlockIterator::create_and_seek_to_key(block, b"key_1");
+    assert_eq!(iter.key(), b"key_1");
+    assert_eq!(iter.value(), b"value_1");
+    assert_eq!(iter.meta(), expires);
+    assert!(!iter.meta().is_expired(999));
+    assert!(iter.meta().is_expired(1000));
+    iter.next();
+    assert_eq!(iter.key(), b"key_2");
+    assert_eq!(iter.value(), b"value_2");
+    assert_eq!(iter.meta(), EntryMeta::default());
+}
+
+#[test]
+fn test_block_max_lengths() {
+    let key = vec![b'k'; MAX_KEY_LEN];
+    let value = vec![b'v'; MAX_VALUE_LEN];
+    let meta = EntryMeta {
+        is_blob_pointer: true,
+        expires_at: Some(1000),
+    };
+    let mut builder = BlockBuilder::new(4096);
+    assert!(builder.add_entry(&key, &value, meta));
+    let block = Arc::new(Block::decode(builder.build().encode()));
+    let iter = BlockIterator::create_and_seek_to_first(block);
+    assert_eq!(iter.key(), &key[..]);
+    assert_eq!(iter.value(), &value[..]);
+    assert_eq!(iter.meta(), meta);
+}
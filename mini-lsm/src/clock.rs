@@ -0,0 +1,59 @@
+//! The source of time for expiring entries and throttling writes, so that tests can control it.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub trait Clock: Send + Sync {
+    /// Get the current time in milliseconds since the Unix epoch.
+    fn now_millis(&self) -> u64;
+
+    /// Block the calling thread until `duration` has passed on this clock.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// The wall clock of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before the Unix epoch")
+            .as_millis() as u64
+    }
+}
+
+/// A clock that only moves when told to, or when slept on.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at `now_millis`.
+    pub fn new(now_millis: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now_millis),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        self.now
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+
+    /// Move the clock forward instead of waiting, so that code sleeping on it runs instantly.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
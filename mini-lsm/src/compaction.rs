@@ -0,0 +1,257 @@
+//! Merging tables into new ones, dropping the entries no reader can see any more.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+
+use crate::blob::{read_blob, BlobFiles};
+use crate::block::{EntryMeta, MAX_VALUE_LEN};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::TableWriter;
+use crate::table::SsTable;
+
+/// What to do with an entry during compaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Keep the entry as it is.
+    Keep,
+    /// Remove the entry, as if it had been deleted.
+    Remove,
+    /// Keep the key with a new value.
+    ChangeValue(Bytes),
+}
+
+/// Decides, for each live entry written by a compaction, whether to keep, remove or rewrite it.
+/// This lets applications garbage-collect data in the background, for example the keys of a
+/// deleted tenant.
+pub trait CompactionFilter: Send + Sync {
+    /// Called with every entry that is neither deleted nor expired, once per compaction it goes
+    /// through. `level` is the level the compaction writes to. Values stored in blob files are
+    /// read before being passed in.
+    fn filter(&self, level: usize, key: &[u8], value: &[u8]) -> FilterDecision;
+}
+
+/// What a compaction needs to know besides its input and output.
+pub(crate) struct CompactionContext<'a> {
+    /// The level the compaction writes to.
+    pub level: usize,
+    /// Whether no level below holds older versions of the keys.
+    pub bottom_level: bool,
+    /// Entries that expire at or before this time are dropped.
+    pub now: u64,
+    pub filter: Option<&'a dyn CompactionFilter>,
+    /// Blob files to read the values passed to the filter from.
+    pub blob_files: &'a BlobFiles,
+}
+
+/// The tables merged by a compaction into L1.
+pub(crate) struct CompactionInputs {
+    /// From earliest to latest.
+    pub l0_sstables: Vec<Arc<SsTable>>,
+    /// Sorted by key range.
+    pub l1_sstables: Vec<Arc<SsTable>>,
+}
+
+/// Pick the tables to merge to compact the keys from `lower` to `upper` into L1. The L0 tables may
+/// overlap each other, so they are all picked if any of them holds keys in the range. L1 tables are
+/// picked if they hold keys between the first and the last key of the other picked tables, so that
+/// the output does not overlap the L1 tables left in place. L0 is also picked if it holds keys in
+/// the span of the picked L1 tables, as the output gets newer IDs than every L0 table, and
+/// recovery orders the tables by ID.
+pub(crate) fn pick_compaction_inputs(
+    l0_sstables: &[Arc<SsTable>],
+    l1_sstables: &[Arc<SsTable>],
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+) -> Result<CompactionInputs> {
+    // Every table overlaps the whole key space, and compactions triggered by L0 use it, so
+    // avoid reading the last key of every table for them.
+    if let (Bound::Unbounded, Bound::Unbounded) = (lower, upper) {
+        return Ok(CompactionInputs {
+            l0_sstables: l0_sstables.to_vec(),
+            l1_sstables: l1_sstables.to_vec(),
+        });
+    }
+    let mut l0_ranges = Vec::new();
+    for table in l0_sstables {
+        l0_ranges.push(table.key_range()?);
+    }
+    let mut l1_ranges = Vec::with_capacity(l1_sstables.len());
+    for table in l1_sstables {
+        l1_ranges.push(table.key_range()?);
+    }
+    let mut pick_l0 = l0_ranges
+        .iter()
+        .any(|(first, last)| overlaps(first, last, lower, upper));
+    let mut hull: Option<(&[u8], &[u8])> = None;
+    for (first, last) in l1_ranges
+        .iter()
+        .filter(|(first, last)| overlaps(first, last, lower, upper))
+    {
+        hull = Some(extend_hull(hull, first, last));
+    }
+    // The output spans from the first to the last key of the picked tables, so it overlaps every
+    // L1 table in between. As L1 tables do not overlap each other, picking those only widens the
+    // span to the keys of the first and the last of them, which may in turn overlap L0.
+    loop {
+        if pick_l0 {
+            for (first, last) in &l0_ranges {
+                hull = Some(extend_hull(hull, first, last));
+            }
+        }
+        if let Some((hull_first, hull_last)) = hull {
+            for (first, last) in &l1_ranges {
+                if overlaps(
+                    first,
+                    last,
+                    Bound::Included(hull_first),
+                    Bound::Included(hull_last),
+                ) {
+                    hull = Some(extend_hull(hull, first, last));
+                }
+            }
+        }
+        let Some((hull_first, hull_last)) = hull else {
+            break;
+        };
+        if pick_l0
+            || !l0_ranges.iter().any(|(first, last)| {
+                overlaps(
+                    first,
+                    last,
+                    Bound::Included(hull_first),
+                    Bound::Included(hull_last),
+                )
+            })
+        {
+            break;
+        }
+        pick_l0 = true;
+    }
+    let mut l1_inputs = Vec::new();
+    if let Some((hull_first, hull_last)) = hull {
+        for (table, (first, last)) in l1_sstables.iter().zip(&l1_ranges) {
+            if overlaps(
+                first,
+                last,
+                Bound::Included(hull_first),
+                Bound::Included(hull_last),
+            ) {
+                l1_inputs.push(table.clone());
+            }
+        }
+    }
+    let l0_inputs = if pick_l0 {
+        l0_sstables.to_vec()
+    } else {
+        Vec::new()
+    };
+    Ok(CompactionInputs {
+        l0_sstables: l0_inputs,
+        l1_sstables: l1_inputs,
+    })
+}
+
+/// Widen `hull` to the keys from `first` to `last`.
+fn extend_hull<'a>(
+    hull: Option<(&'a [u8], &'a [u8])>,
+    first: &'a [u8],
+    last: &'a [u8],
+) -> (&'a [u8], &'a [u8]) {
+    match hull {
+        Some((hull_first, hull_last)) => (hull_first.min(first), hull_last.max(last)),
+        None => (first, last),
+    }
+}
+
+/// Check if the keys from `first` to `last` overlap the range from `lower` to `upper`.
+fn overlaps(first: &[u8], last: &[u8], lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+    let after_lower = match lower {
+        Bound::Included(key) => last >= key,
+        Bound::Excluded(key) => last > key,
+        Bound::Unbounded => true,
+    };
+    let before_upper = match upper {
+        Bound::Included(key) => first <= key,
+        Bound::Excluded(key) => first < key,
+        Bound::Unbounded => true,
+    };
+    after_lower && before_upper
+}
+
+/// Choose the keys splitting a compaction of `tables` into up to `max_subcompactions` key ranges
+/// that can be compacted in parallel. The split points are first keys of the blocks of the
+/// tables, spread so that each range starts about as many blocks.
+pub(crate) fn subcompaction_boundaries(
+    tables: &[Arc<SsTable>],
+    max_subcompactions: usize,
+) -> Vec<Bytes> {
+    let mut keys = tables
+        .iter()
+        .flat_map(|table| table.block_meta())
+        .map(|meta| meta.first_key.clone())
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    let ranges = max_subcompactions.min(keys.len()).max(1);
+    (1..ranges)
+        .map(|range| keys[range * keys.len() / ranges].clone())
+        .collect()
+}
+
+/// Write the entries of `iter` to `writer`. `iter` yields the latest version of each key of the
+/// tables being compacted, so older versions are dropped. Expired entries and entries removed by
+/// the compaction filter are dropped as well, and so are tombstones if the output is the bottom
+/// level. Above the bottom level, they all have to be kept as tombstones to hide the older
+/// versions in lower levels. Writing stops before `end`, if given.
+pub(crate) fn compact_entries(
+    mut iter: impl StorageIterator,
+    end: Option<&[u8]>,
+    writer: &mut TableWriter,
+    context: &CompactionContext,
+) -> Result<()> {
+    while iter.is_valid() && !matches!(end, Some(end) if iter.key() >= end) {
+        let meta = iter.meta();
+        let mut is_deleted =
+            meta.is_expired(context.now) || (iter.value().is_empty() && !meta.is_blob_pointer);
+        let mut new_value = None;
+        if let (false, Some(filter)) = (is_deleted, context.filter) {
+            let blob_value;
+            let value = if meta.is_blob_pointer {
+                blob_value = read_blob(context.blob_files, iter.value())?;
+                &blob_value[..]
+            } else {
+                iter.value()
+            };
+            match filter.filter(context.level, iter.key(), value) {
+                FilterDecision::Keep => {}
+                FilterDecision::Remove => is_deleted = true,
+                FilterDecision::ChangeValue(value) => new_value = Some(value),
+            }
+        }
+        if is_deleted {
+            if !context.bottom_level {
+                writer.add_entry(iter.key(), b"", EntryMeta::default())?;
+            }
+        } else if let Some(value) = new_value {
+            // Changed values are stored inline, so they must fit in a block entry.
+            if value.is_empty() || value.len() > MAX_VALUE_LEN {
+                bail!(
+                    "compaction filter changed the value to an invalid size: {} bytes",
+                    value.len()
+                );
+            }
+            let meta = EntryMeta {
+                is_blob_pointer: false,
+                ..meta
+            };
+            writer.add_entry(iter.key(), &value, meta)?;
+        } else {
+            writer.add_entry(iter.key(), iter.value(), meta)?;
+        }
+        iter.next()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! Callbacks notified of what the storage does with its files, for example to back up new
+//! tables or to log flushes and compactions.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::lsm_storage::WriteStallCause;
+
+/// An SST that was created or deleted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableFileInfo {
+    pub column_family: String,
+    pub sst_id: usize,
+    /// The level the table belongs to, 0 for L0.
+    pub level: usize,
+    pub first_key: Bytes,
+    pub last_key: Bytes,
+    /// Size of the table file in bytes.
+    pub file_size: u64,
+}
+
+/// A flush of one immutable memtable into L0 tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlushJobInfo {
+    pub column_family: String,
+    /// ID of the first table being written.
+    pub sst_id: usize,
+    /// Bytes put into the memtable being flushed.
+    pub memtable_bytes: u64,
+    /// The tables written, in key order, once the flush has completed.
+    pub tables: Vec<TableFileInfo>,
+    /// How long the flush took, or zero when it begins.
+    pub duration: Duration,
+}
+
+/// A compaction of L0 and L1 into a new L1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionJobInfo {
+    pub column_family: String,
+    /// The tables being compacted.
+    pub input_tables: Vec<TableFileInfo>,
+    /// The level the compaction writes to.
+    pub output_level: usize,
+    /// The tables written, once the compaction has completed. Empty if every entry was dropped.
+    pub output_tables: Vec<TableFileInfo>,
+    /// How long the compaction took, or zero when it begins.
+    pub duration: Duration,
+}
+
+/// A write delayed to let flushes and compactions catch up, or stopped to flush or compact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteStallInfo {
+    pub cause: WriteStallCause,
+    /// Whether the write was stopped by a stop trigger rather than delayed by a slowdown one.
+    pub stopped: bool,
+}
+
+/// Receives the events of a storage. Register it in
+/// [`LsmStorageOptions::event_listeners`](crate::lsm_storage::LsmStorageOptions::event_listeners).
+///
+/// The callbacks run on the thread doing the work, with the flush lock held for flushes,
+/// compactions and table events, so they should return quickly and must not call back into the
+/// storage.
+pub trait EventListener: Send + Sync {
+    fn on_flush_begin(&self, _info: &FlushJobInfo) {}
+
+    fn on_flush_completed(&self, _info: &FlushJobInfo) {}
+
+    fn on_compaction_begin(&self, _info: &CompactionJobInfo) {}
+
+    fn on_compaction_completed(&self, _info: &CompactionJobInfo) {}
+
+    /// Called once a new table is durable and visible to readers.
+    fn on_table_file_created(&self, _info: &TableFileInfo) {}
+
+    /// Called once a table that is no longer used has been deleted.
+    fn on_table_file_deleted(&self, _info: &TableFileInfo) {}
+
+    /// Called for every write that is delayed or blocked.
+    fn on_write_stall(&self, _info: &WriteStallInfo) {}
+}
@@ -1,6 +1,8 @@
 pub mod merge_iterator;
 pub mod two_merge_iterator;
 
+use crate::block::EntryMeta;
+
 pub trait StorageIterator {
     /// Get the current value.
     fn value(&self) -> &[u8];
@@ -11,6 +13,12 @@ pub trait StorageIterator {
     /// Check if the current iterator is valid.
     fn is_valid(&self) -> bool;
 
+    /// Get the attributes of the current entry, such as whether the value is a pointer into a
+    /// blob file and when the entry expires.
+    fn meta(&self) -> EntryMeta {
+        EntryMeta::default()
+    }
+
     /// Move to the next position.
     fn next(&mut self) -> anyhow::Result<()>;
 }
@@ -5,12 +5,13 @@ use std::collections::BinaryHeap;
 use anyhow::Result;
 
 use super::StorageIterator;
+use crate::block::EntryMeta;
 
 struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
 
 impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
     fn eq(&self, other: &Self) -> bool {
-        self.partial_cmp(other).unwrap() == cmp::Ordering::Equal
+        self.cmp(other) == cmp::Ordering::Equal
     }
 }
 
@@ -18,18 +19,18 @@ impl<I: StorageIterator> Eq for HeapWrapper<I> {}
 
 impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        match self.1.key().cmp(other.1.key()) {
-            cmp::Ordering::Greater => Some(cmp::Ordering::Greater),
-            cmp::Ordering::Less => Some(cmp::Ordering::Less),
-            cmp::Ordering::Equal => self.0.partial_cmp(&other.0),
-        }
-        .map(|x| x.reverse())
+        Some(self.cmp(other))
     }
 }
 
 impl<I: StorageIterator> Ord for HeapWrapper<I> {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        match self.1.key().cmp(other.1.key()) {
+            cmp::Ordering::Greater => cmp::Ordering::Greater,
+            cmp::Ordering::Less => cmp::Ordering::Less,
+            cmp::Ordering::Equal => self.0.cmp(&other.0),
+        }
+        .reverse()
     }
 }
 
@@ -85,6 +86,10 @@ impl<I: StorageIterator> StorageIterator for MergeIterator<I> {
             .value()
     }
 
+    fn meta(&self) -> EntryMeta {
+        unsafe { self.current.as_ref().unwrap_unchecked() }.1.meta()
+    }
+
     fn is_valid(&self) -> bool {
         self.current
             .as_ref()
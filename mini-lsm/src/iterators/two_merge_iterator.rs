@@ -1,6 +1,7 @@
 use anyhow::Result;
 
 use super::StorageIterator;
+use crate::block::EntryMeta;
 
 /// Merges two iterators of different types into one. If the two iterators have the same key, only
 /// produce the key once and prefer the entry from A.
@@ -59,6 +60,14 @@ impl<A: StorageIterator, B: StorageIterator> StorageIterator for TwoMergeIterato
         }
     }
 
+    fn meta(&self) -> EntryMeta {
+        if self.choose_a {
+            self.a.meta()
+        } else {
+            self.b.meta()
+        }
+    }
+
     fn is_valid(&self) -> bool {
         if self.choose_a {
             self.a.is_valid()
@@ -1,9 +1,19 @@
+pub mod backend;
+pub mod blob;
 pub mod block;
+pub mod clock;
+pub mod compaction;
+pub mod event_listener;
 pub mod iterators;
 pub mod lsm_iterator;
 pub mod lsm_storage;
 pub mod mem_table;
+pub mod metrics;
+pub mod rate_limiter;
+pub mod read_options;
 pub mod table;
+mod trace;
+pub mod write_batch;
 
 #[cfg(test)]
 mod tests;
@@ -1,8 +1,10 @@
 use std::ops::Bound;
+use std::sync::Arc;
 
 use anyhow::Result;
 use bytes::Bytes;
 
+use crate::blob::{read_blob, BlobFiles};
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
@@ -16,16 +18,32 @@ pub struct LsmIterator {
     iter: LsmIteratorInner,
     end_bound: Bound<Bytes>,
     is_valid: bool,
+    /// Blob files the values stored out of line are read from.
+    blob_files: Arc<BlobFiles>,
+    /// The current value, if it is stored in a blob file.
+    blob_value: Option<Bytes>,
+    /// Entries that expire at or before this time, in milliseconds since the Unix epoch, are
+    /// hidden.
+    now: u64,
 }
 
 impl LsmIterator {
-    pub(crate) fn new(iter: LsmIteratorInner, end_bound: Bound<Bytes>) -> Result<Self> {
+    pub(crate) fn new(
+        iter: LsmIteratorInner,
+        end_bound: Bound<Bytes>,
+        blob_files: Arc<BlobFiles>,
+        now: u64,
+    ) -> Result<Self> {
         let mut iter = Self {
             is_valid: iter.is_valid(),
             iter,
             end_bound,
+            blob_files,
+            blob_value: None,
+            now,
         };
         iter.move_to_non_delete()?;
+        iter.resolve_blob()?;
         Ok(iter)
     }
 
@@ -44,11 +62,26 @@ impl LsmIterator {
     }
 
     fn move_to_non_delete(&mut self) -> Result<()> {
-        while self.is_valid() && self.iter.value().is_empty() {
+        while self.is_valid() && self.is_deleted() {
             self.next_inner()?;
         }
         Ok(())
     }
+
+    /// Check if the current entry is a tombstone or has expired.
+    fn is_deleted(&self) -> bool {
+        let meta = self.iter.meta();
+        (self.iter.value().is_empty() && !meta.is_blob_pointer) || meta.is_expired(self.now)
+    }
+
+    /// Read the current value from its blob file if the entry only holds a pointer to it.
+    fn resolve_blob(&mut self) -> Result<()> {
+        self.blob_value = None;
+        if self.is_valid() && self.iter.meta().is_blob_pointer {
+            self.blob_value = Some(read_blob(&self.blob_files, self.iter.value())?);
+        }
+        Ok(())
+    }
 }
 
 impl StorageIterator for LsmIterator {
@@ -61,12 +94,16 @@ impl StorageIterator for LsmIterator {
     }
 
     fn value(&self) -> &[u8] {
-        self.iter.value()
+        match &self.blob_value {
+            Some(value) => value,
+            None => self.iter.value(),
+        }
     }
 
     fn next(&mut self) -> Result<()> {
         self.next_inner()?;
         self.move_to_non_delete()?;
+        self.resolve_blob()?;
         Ok(())
     }
 }
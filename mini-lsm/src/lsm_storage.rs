@@ -1,20 +1,49 @@
+use std::collections::BTreeMap;
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use bytes::Bytes;
 use parking_lot::{Mutex, RwLock};
 
-use crate::block::Block;
+use crate::backend::{LocalBackend, StorageBackend};
+use crate::blob::{read_blob, read_blob_records, BlobFileBuilder, BlobFiles};
+use crate::block::{EntryMeta, MAX_KEY_LEN, MAX_VALUE_LEN};
+use crate::clock::{Clock, SystemClock};
+use crate::compaction::{
+    compact_entries, pick_compaction_inputs, subcompaction_boundaries, CompactionContext,
+    CompactionFilter, CompactionInputs,
+};
+use crate::event_listener::{CompactionJobInfo, EventListener, FlushJobInfo, TableFileInfo};
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
 use crate::lsm_iterator::{FusedIterator, LsmIterator};
 use crate::mem_table::{map_bound, MemTable};
-use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::metrics::{render_stats, Metrics, Operation, OperationTimer};
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::read_options::ReadOptions;
+use crate::table::{CompressionType, FileObject, SsTable, SsTableBuilder, SsTableIterator};
+use crate::trace::enter_span;
+use crate::write_batch::{WriteBatch, WriteOp};
 
-pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
+mod block_cache;
+mod multi_get;
+mod recovery;
+mod stats;
+mod table_writer;
+mod write_stall;
+
+pub use block_cache::BlockCache;
+use recovery::{check_column_family_name, file_name};
+use stats::StatsCounters;
+pub use stats::{FileStats, StorageStats};
+pub(crate) use table_writer::TableWriter;
+use write_stall::WriteStallCounters;
+pub use write_stall::{WriteStallCause, WriteStallStats};
 
 #[derive(Clone)]
 pub struct LsmStorageInner {
@@ -25,10 +54,9 @@ pub struct LsmStorageInner {
     /// L0 SsTables, from earliest to latest.
     l0_sstables: Vec<Arc<SsTable>>,
     /// L1 - L6 SsTables, sorted by key range.
-    #[allow(dead_code)]
     levels: Vec<Vec<Arc<SsTable>>>,
-    /// The next SSTable ID.
-    next_sst_id: usize,
+    /// Blob files holding the values separated from the SSTs.
+    blob_files: Arc<BlobFiles>,
 }
 
 impl LsmStorageInner {
@@ -38,144 +66,1078 @@ impl LsmStorageInner {
             imm_memtables: vec![],
             l0_sstables: vec![],
             levels: vec![],
-            next_sst_id: 1,
+            blob_files: Arc::new(BlobFiles::new()),
+        }
+    }
+}
+
+/// A memtable written to L0 tables by a flush that is not committed yet.
+struct FlushedMemtable<'a> {
+    column_family: &'a ColumnFamily,
+    ssts: Vec<Arc<SsTable>>,
+    blob_file: Option<FileObject>,
+    info: FlushJobInfo,
+    start: Instant,
+}
+
+/// The name of the column family that the methods without a column family operate on.
+pub const DEFAULT_COLUMN_FAMILY: &str = "default";
+
+/// A named, independent set of memtables and SSTs. All column families of a storage share its
+/// block cache and SST IDs, and are flushed and compacted together by `sync`.
+struct ColumnFamily {
+    name: String,
+    inner: RwLock<Arc<LsmStorageInner>>,
+}
+
+impl ColumnFamily {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            inner: RwLock::new(Arc::new(LsmStorageInner::create())),
+        }
+    }
+
+    fn snapshot(&self) -> Arc<LsmStorageInner> {
+        let guard = self.inner.read();
+        Arc::clone(&guard)
+    }
+}
+
+/// Options for opening an [`LsmStorage`].
+#[derive(Clone)]
+pub struct LsmStorageOptions {
+    /// Where SST files are stored.
+    pub backend: Arc<dyn StorageBackend>,
+    /// Block compression of each level, starting from L0. Levels beyond the end use the last
+    /// entry, so `vec![None, Lz4, Zstd]` compresses L2 and all lower levels with zstd.
+    pub compression_per_level: Vec<CompressionType>,
+    /// Maximum size of the zstd dictionary trained for each SST and shared by its blocks, or 0 to
+    /// compress blocks on their own.
+    pub zstd_dictionary_size: usize,
+    /// Values of at least this many bytes are moved to blob files when flushed, and SSTs only store
+    /// pointers to them. 0 keeps all values inline.
+    pub min_blob_size: usize,
+    /// Blob garbage collection rewrites a blob file once at least this fraction of its bytes
+    /// belongs to overwritten or deleted values.
+    pub blob_gc_garbage_ratio: f64,
+    /// The clock entries written with a time-to-live expire by, and that `write_bytes_per_second`
+    /// is measured on.
+    pub clock: Arc<dyn Clock>,
+    /// Compact L0 into L1 once it has this many tables. 0 disables automatic compaction.
+    pub level0_compaction_trigger: usize,
+    /// Delay every write by `write_slowdown_delay` once L0 has this many tables. 0 disables.
+    pub level0_slowdown_writes_trigger: usize,
+    /// Stop writes once L0 has this many tables, until the stopped writer compacts it. 0
+    /// disables.
+    pub level0_stop_writes_trigger: usize,
+    /// Stop writes once this many immutable memtables wait to be flushed, until the stopped writer
+    /// flushes them. 0 disables.
+    pub max_imm_memtables: usize,
+    /// Delay every write once the compaction that is due would rewrite this many bytes. 0
+    /// disables.
+    pub soft_pending_compaction_bytes_limit: u64,
+    /// Stop writes once the compaction that is due would rewrite this many bytes, until the
+    /// stopped writer compacts. 0 disables.
+    pub hard_pending_compaction_bytes_limit: u64,
+    /// How long a write is delayed by a slowdown trigger.
+    pub write_slowdown_delay: Duration,
+    /// Flushes and compactions start a new SST once the one they write reaches this many bytes.
+    /// 0 writes each flush, or each key range of a compaction, into a single SST.
+    pub target_file_size: usize,
+    /// Split each compaction into up to this many key ranges compacted in parallel, each into its
+    /// own SST. 1 compacts everything into a single SST.
+    pub max_subcompactions: usize,
+    /// Decides which entries compactions keep, remove or rewrite.
+    pub compaction_filter: Option<Arc<dyn CompactionFilter>>,
+    /// Limit the bytes flushes, compactions and blob garbage collection write per second, shared
+    /// by all column families. Flushes go first. 0 disables.
+    pub write_bytes_per_second: u64,
+    /// Latency histograms to record the operations and background jobs into.
+    pub metrics: Option<Arc<Metrics>>,
+    /// Notified of flushes, compactions, table files and write stalls, in order.
+    pub event_listeners: Vec<Arc<dyn EventListener>>,
+}
+
+impl LsmStorageOptions {
+    /// Get the block compression of SSTs written to `level`.
+    pub fn compression_of_level(&self, level: usize) -> CompressionType {
+        self.compression_per_level
+            .get(level)
+            .or_else(|| self.compression_per_level.last())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Check if a value of `len` bytes is moved to a blob file.
+    fn is_separated(&self, len: usize) -> bool {
+        self.min_blob_size > 0 && len >= self.min_blob_size
+    }
+}
+
+impl Default for LsmStorageOptions {
+    fn default() -> Self {
+        Self {
+            backend: Arc::new(LocalBackend::default()),
+            compression_per_level: vec![CompressionType::None],
+            zstd_dictionary_size: 0,
+            min_blob_size: 0,
+            blob_gc_garbage_ratio: 0.5,
+            clock: Arc::new(SystemClock),
+            level0_compaction_trigger: 0,
+            level0_slowdown_writes_trigger: 20,
+            level0_stop_writes_trigger: 36,
+            max_imm_memtables: 8,
+            soft_pending_compaction_bytes_limit: 64 << 30,
+            hard_pending_compaction_bytes_limit: 256 << 30,
+            write_slowdown_delay: Duration::from_millis(1),
+            target_file_size: 64 << 20,
+            max_subcompactions: 1,
+            compaction_filter: None,
+            write_bytes_per_second: 0,
+            metrics: None,
+            event_listeners: Vec::new(),
         }
     }
 }
 
 /// The storage interface of the LSM tree.
 pub struct LsmStorage {
-    inner: Arc<RwLock<Arc<LsmStorageInner>>>,
+    column_families: RwLock<BTreeMap<String, Arc<ColumnFamily>>>,
+    /// Held shared while writing to memtables, and exclusively while `sync` freezes them, so
+    /// that a write batch lands in the memtables of the same generation in every column family.
+    write_lock: RwLock<()>,
+    /// Held exclusively while a write batch is applied, and shared while `multi_get` searches
+    /// the memtables, so that it sees all of a batch or none of it.
+    batch_lock: RwLock<()>,
     flush_lock: Mutex<()>,
+    /// The marker ID of a flush that failed, if its files could not all be deleted yet.
+    abandoned_flush: Mutex<Option<usize>>,
+    write_stall_counters: WriteStallCounters,
+    stats_counters: StatsCounters,
+    /// The next ID of an SST or blob file, shared by all column families.
+    next_sst_id: AtomicUsize,
+    rate_limiter: Arc<RateLimiter>,
     path: PathBuf,
     block_cache: Arc<BlockCache>,
+    options: LsmStorageOptions,
 }
 
 impl LsmStorage {
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            inner: Arc::new(RwLock::new(Arc::new(LsmStorageInner::create()))),
+        Self::open_with_options(path, LsmStorageOptions::default())
+    }
+
+    /// Open the storage in `path`, reopening the tables left there by a previous run.
+    pub fn open_with_options(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
+        let mut column_families = BTreeMap::new();
+        column_families.insert(
+            DEFAULT_COLUMN_FAMILY.to_string(),
+            Arc::new(ColumnFamily::new(DEFAULT_COLUMN_FAMILY)),
+        );
+        let storage = Self {
+            column_families: RwLock::new(column_families),
+            write_lock: RwLock::new(()),
+            batch_lock: RwLock::new(()),
             flush_lock: Mutex::new(()),
+            abandoned_flush: Mutex::new(None),
+            write_stall_counters: WriteStallCounters::default(),
+            stats_counters: StatsCounters::default(),
+            next_sst_id: AtomicUsize::new(1),
+            rate_limiter: Arc::new(RateLimiter::new(
+                options.write_bytes_per_second,
+                options.clock.clone(),
+            )),
             path: path.as_ref().to_path_buf(),
             block_cache: Arc::new(BlockCache::new(1 << 20)), // 4GB block cache
+            options,
+        };
+        storage.recover()?;
+        Ok(storage)
+    }
+
+    /// Change the limit of `write_bytes_per_second` while the storage is open. 0 removes it.
+    pub fn set_write_rate_limit(&self, bytes_per_second: u64) {
+        self.rate_limiter.set_bytes_per_second(bytes_per_second);
+    }
+
+    /// Start timing `operation` if metrics are enabled.
+    fn start_timer(&self, operation: Operation) -> Option<OperationTimer<'_>> {
+        let metrics = self.options.metrics.as_ref()?;
+        Some(metrics.start_timer(operation))
+    }
+
+    /// Render the statistics of the storage, followed by the latency histograms if metrics are
+    /// enabled, in the Prometheus text exposition format.
+    pub fn render_metrics(&self) -> String {
+        let mut out = render_stats(&self.stats());
+        if let Some(metrics) = &self.options.metrics {
+            out.push_str(&metrics.render());
+        }
+        out
+    }
+
+    /// Call `event` on every event listener.
+    fn notify(&self, event: impl Fn(&dyn EventListener)) {
+        for listener in &self.options.event_listeners {
+            event(listener.as_ref());
+        }
+    }
+
+    /// Describe `table` of `level` for the event listeners. The key range is only read if there
+    /// is any listener.
+    fn table_file_info(
+        &self,
+        column_family: &ColumnFamily,
+        table: &SsTable,
+        level: usize,
+    ) -> Result<TableFileInfo> {
+        let (first_key, last_key) = if self.options.event_listeners.is_empty() {
+            Default::default()
+        } else {
+            table.key_range()?
+        };
+        Ok(TableFileInfo {
+            column_family: column_family.name.clone(),
+            sst_id: table.sst_id(),
+            level,
+            first_key,
+            last_key,
+            file_size: table.table_size(),
         })
     }
 
+    /// Create a new, empty column family. Its name may only hold ASCII letters, digits, '_' and
+    /// '-', as it prefixes the names of its files.
+    pub fn create_column_family(&self, name: &str) -> Result<()> {
+        check_column_family_name(name)?;
+        let mut column_families = self.column_families.write();
+        if column_families.contains_key(name) {
+            bail!("column family {} already exists", name);
+        }
+        column_families.insert(name.to_string(), Arc::new(ColumnFamily::new(name)));
+        Ok(())
+    }
+
+    /// Get the names of all column families, including the default one.
+    pub fn column_families(&self) -> Vec<String> {
+        self.column_families.read().keys().cloned().collect()
+    }
+
+    fn column_family(&self, name: &str) -> Result<Arc<ColumnFamily>> {
+        self.column_families
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("column family {} does not exist", name))
+    }
+
     /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
     pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
-        let snapshot = {
-            let guard = self.inner.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+        self.get_cf(DEFAULT_COLUMN_FAMILY, key)
+    }
+
+    /// Get a key from the given column family.
+    pub fn get_cf(&self, column_family: &str, key: &[u8]) -> Result<Option<Bytes>> {
+        self.get_cf_with_options(column_family, key, &ReadOptions::default())
+    }
+
+    /// Get a key from the storage with the given read options.
+    pub fn get_with_options(&self, key: &[u8], options: &ReadOptions) -> Result<Option<Bytes>> {
+        self.get_cf_with_options(DEFAULT_COLUMN_FAMILY, key, options)
+    }
+
+    /// Get a key from the given column family with the given read options.
+    pub fn get_cf_with_options(
+        &self,
+        column_family: &str,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<Bytes>> {
+        let _timer = self.start_timer(Operation::Get);
+        enter_span!("get", column_family, key_len = key.len());
+        let snapshot = self.column_family(column_family)?.snapshot();
+        let entry = Self::get_entry(&snapshot, key, options)?;
+        Self::resolve_entry(&snapshot, entry, self.options.clock.now_millis())
+    }
 
-        // Search on the current memtable.
-        if let Some(value) = snapshot.memtable.get(key) {
-            if value.is_empty() {
-                // found tomestone, return key not exists
-                return Ok(None);
+    /// Get the value a `get` returns for the latest entry of a key.
+    fn resolve_entry(
+        snapshot: &LsmStorageInner,
+        entry: Option<(Bytes, EntryMeta)>,
+        now: u64,
+    ) -> Result<Option<Bytes>> {
+        match entry {
+            // An expired entry hides older versions of the key, like a tombstone.
+            Some((_, meta)) if meta.is_expired(now) => Ok(None),
+            Some((pointer, meta)) if meta.is_blob_pointer => {
+                Ok(Some(read_blob(&snapshot.blob_files, &pointer)?))
             }
-            return Ok(Some(value));
+            // An empty value is a tombstone: the key does not exist.
+            Some((value, _)) if !value.is_empty() => Ok(Some(value)),
+            _ => Ok(None),
         }
-        // Search on immutable memtables.
-        for memtable in snapshot.imm_memtables.iter().rev() {
-            if let Some(value) = memtable.get(key) {
-                if value.is_empty() {
-                    // found tomestone, return key not exists
-                    return Ok(None);
-                }
-                return Ok(Some(value));
-            }
+    }
+
+    /// Find the latest entry of `key`, without resolving blob pointers or checking whether it has
+    /// expired.
+    fn get_entry(
+        snapshot: &LsmStorageInner,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<(Bytes, EntryMeta)>> {
+        if let Some(entry) = Self::get_memtable_entry(snapshot, key, options) {
+            return Ok(Some(entry));
         }
-        let mut iters = Vec::new();
-        iters.reserve(snapshot.l0_sstables.len());
-        for table in snapshot.l0_sstables.iter().rev() {
-            iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
-                table.clone(),
-                key,
-            )?));
+        enter_span!(
+            "search_sstables",
+            l0_sstables = snapshot.l0_sstables.len(),
+            levels = snapshot.levels.len()
+        );
+        let mut iters = Vec::with_capacity(snapshot.l0_sstables.len() + snapshot.levels.len());
+        for table in Self::sstables_for_key(snapshot, key, options) {
+            options.count(|perf| &perf.sstables_checked, 1);
+            iters.push(Box::new(
+                SsTableIterator::create_and_seek_to_key_with_options(
+                    table.clone(),
+                    key,
+                    options.clone(),
+                )?,
+            ));
         }
         let iter = MergeIterator::create(iters);
-        if iter.is_valid() {
-            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+        if iter.is_valid() && iter.key() == key {
+            return Ok(Some((Bytes::copy_from_slice(iter.value()), iter.meta())));
         }
         Ok(None)
     }
 
+    /// Get the SSTs that may contain `key`, from the latest to the earliest. L0 tables overlap, so
+    /// all of them starting at or before `key` are returned, but each lower level has at most one
+    /// table covering `key`, which is found by binary search. The other tables are counted as
+    /// skipped.
+    fn sstables_for_key<'a>(
+        snapshot: &'a LsmStorageInner,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Vec<&'a Arc<SsTable>> {
+        let mut tables = Vec::with_capacity(snapshot.l0_sstables.len() + snapshot.levels.len());
+        let l0_sstables = snapshot.l0_sstables.iter().rev();
+        tables.extend(l0_sstables.filter(|table| table.first_key() <= key));
+        let mut skipped = snapshot.l0_sstables.len() - tables.len();
+        for level in &snapshot.levels {
+            let idx = level.partition_point(|table| table.first_key() <= key);
+            skipped += level.len();
+            if idx > 0 {
+                tables.push(&level[idx - 1]);
+                skipped -= 1;
+            }
+        }
+        options.count(|perf| &perf.sstables_skipped, skipped as u64);
+        tables
+    }
+
+    /// Find the latest entry of `key` in the memtables.
+    fn get_memtable_entry(
+        snapshot: &LsmStorageInner,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Option<(Bytes, EntryMeta)> {
+        let memtable_entry = |memtable: &MemTable| {
+            options.count(|perf| &perf.memtables_probed, 1);
+            memtable.get_with_expiry(key).map(|(value, expires_at)| {
+                let meta = EntryMeta {
+                    is_blob_pointer: false,
+                    expires_at,
+                };
+                (value, meta)
+            })
+        };
+        enter_span!(
+            "search_memtables",
+            imm_memtables = snapshot.imm_memtables.len()
+        );
+        // Search on the current memtable, then on immutable memtables.
+        memtable_entry(&snapshot.memtable).or_else(|| {
+            snapshot
+                .imm_memtables
+                .iter()
+                .rev()
+                .find_map(|memtable| memtable_entry(memtable))
+        })
+    }
+
     /// Put a key-value pair into the storage by writing into the current memtable.
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        assert!(!value.is_empty(), "value cannot be empty");
-        assert!(!key.is_empty(), "key cannot be empty");
+        self.put_cf(DEFAULT_COLUMN_FAMILY, key, value)
+    }
 
-        let guard = self.inner.read();
+    /// Put a key-value pair into the given column family.
+    pub fn put_cf(&self, column_family: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        let _timer = self.start_timer(Operation::Put);
+        enter_span!(
+            "put",
+            column_family,
+            key_len = key.len(),
+            value_len = value.len()
+        );
+        self.check_put(key, value)?;
+        let column_family = self.column_family(column_family)?;
+        self.stall_writes(std::slice::from_ref(&column_family))?;
+
+        let _write_lock = self.write_lock.read();
+        let guard = column_family.inner.read();
         guard.memtable.put(key, value);
+        self.record_user_write(key, value);
+
+        Ok(())
+    }
+
+    /// Put a key-value pair that stops being visible once `ttl` has passed on the clock of the
+    /// storage.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        self.put_with_ttl_cf(DEFAULT_COLUMN_FAMILY, key, value, ttl)
+    }
+
+    /// Put a key-value pair with a time-to-live into the given column family.
+    pub fn put_with_ttl_cf(
+        &self,
+        column_family: &str,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<()> {
+        let _timer = self.start_timer(Operation::Put);
+        enter_span!(
+            "put_with_ttl",
+            column_family,
+            key_len = key.len(),
+            value_len = value.len(),
+            ttl_ms = ttl.as_millis() as u64
+        );
+        self.check_put(key, value)?;
+        let column_family = self.column_family(column_family)?;
+        self.stall_writes(std::slice::from_ref(&column_family))?;
+        let expires_at = self.options.clock.now_millis() + ttl.as_millis() as u64;
+
+        let _write_lock = self.write_lock.read();
+        let guard = column_family.inner.read();
+        guard.memtable.put_with_expiry(key, value, expires_at);
+        self.record_user_write(key, value);
 
         Ok(())
     }
 
     /// Remove a key from the storage by writing an empty value.
     pub fn delete(&self, key: &[u8]) -> Result<()> {
-        assert!(!key.is_empty(), "key cannot be empty");
+        self.delete_cf(DEFAULT_COLUMN_FAMILY, key)
+    }
 
-        let guard = self.inner.read();
+    /// Remove a key from the given column family.
+    pub fn delete_cf(&self, column_family: &str, key: &[u8]) -> Result<()> {
+        let _timer = self.start_timer(Operation::Delete);
+        enter_span!("delete", column_family, key_len = key.len());
+        self.check_key(key)?;
+        let column_family = self.column_family(column_family)?;
+        self.stall_writes(std::slice::from_ref(&column_family))?;
+
+        let _write_lock = self.write_lock.read();
+        let guard = column_family.inner.read();
         guard.memtable.put(key, b"");
+        self.record_user_write(key, b"");
 
         Ok(())
     }
 
-    fn path_of_sst(&self, id: usize) -> PathBuf {
-        self.path.join(format!("{:05}.sst", id))
+    /// Apply the updates of a batch, which may span column families. Nothing is written if any
+    /// update is invalid. `multi_get` sees either all or none of the updates, but a scan reads
+    /// the memtables as they change, so it may see only some of them. The batch is never split
+    /// by `sync`: its updates are flushed together, and a crash keeps either all or none of them.
+    pub fn write(&self, batch: &WriteBatch) -> Result<()> {
+        let _timer = self.start_timer(Operation::Write);
+        enter_span!("write", ops = batch.len());
+        let mut updates = Vec::with_capacity(batch.len());
+        for op in &batch.ops {
+            let (column_family, key, value) = match op {
+                WriteOp::Put {
+                    column_family,
+                    key,
+                    value,
+                } => {
+                    self.check_put(key, value)?;
+                    (column_family, key, &value[..])
+                }
+                WriteOp::Delete { column_family, key } => {
+                    self.check_key(key)?;
+                    (column_family, key, &b""[..])
+                }
+            };
+            updates.push((self.column_family(column_family)?, key, value));
+        }
+        let column_families: Vec<_> = updates.iter().map(|(cf, _, _)| cf.clone()).collect();
+        self.stall_writes(&column_families)?;
+
+        let _write_lock = self.write_lock.read();
+        let _batch_lock = self.batch_lock.write();
+        for (column_family, key, value) in updates {
+            column_family.inner.read().memtable.put(key, value);
+            self.record_user_write(key, value);
+        }
+
+        Ok(())
+    }
+
+    fn record_user_write(&self, key: &[u8], value: &[u8]) {
+        let counter = &self.stats_counters.user_bytes_written;
+        StatsCounters::add(counter, (key.len() + value.len()) as u64);
+    }
+
+    fn check_key(&self, key: &[u8]) -> Result<()> {
+        assert!(!key.is_empty(), "key cannot be empty");
+        if key.len() > MAX_KEY_LEN {
+            bail!("key of {} bytes is too large", key.len());
+        }
+        Ok(())
+    }
+
+    fn check_put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        assert!(!value.is_empty(), "value cannot be empty");
+        self.check_key(key)?;
+        if value.len() > MAX_VALUE_LEN && !self.options.is_separated(value.len()) {
+            bail!(
+                "value of {} bytes is too large to store inline, set `min_blob_size`",
+                value.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Get the path of an SST of `column_family`. The files of all column families share the
+    /// directory of the storage, so the names of the other column families prefix the ID.
+    fn path_of_sst(&self, column_family: &str, id: usize) -> PathBuf {
+        self.path.join(file_name(column_family, id, "sst"))
+    }
+
+    fn path_of_blob(&self, column_family: &str, id: usize) -> PathBuf {
+        self.path.join(file_name(column_family, id, "blob"))
     }
 
     /// Persist data to disk.
     ///
     /// In day 3: flush the current memtable to disk as L0 SST.
     /// In day 6: call `fsync` on WAL.
+    ///
+    /// Returns only after every flushed SST and its directory entry are durable.
     pub fn sync(&self) -> Result<()> {
+        let _timer = self.start_timer(Operation::Sync);
+        enter_span!("sync");
+        let _flush_lock = self.flush_lock.lock();
+        let column_families: Vec<_> = self.column_families.read().values().cloned().collect();
+
+        // Move mutable memtables to immutable memtables, in all column families at once.
+        {
+            let _write_lock = self.write_lock.write();
+            for column_family in &column_families {
+                let mut guard = column_family.inner.write();
+                if !guard.memtable.is_empty() {
+                    // Swap the current memtable with a new one.
+                    let mut snapshot = guard.as_ref().clone();
+                    let memtable =
+                        std::mem::replace(&mut snapshot.memtable, Arc::new(MemTable::create()));
+                    // Add the memtable to the immutable memtables.
+                    snapshot.imm_memtables.push(memtable);
+                    // Update the snapshot.
+                    *guard = Arc::new(snapshot);
+                }
+            }
+        }
+
+        self.flush_imm_memtables(&column_families)?;
+        for column_family in &column_families {
+            let trigger = self.options.level0_compaction_trigger;
+            if trigger > 0 && column_family.inner.read().l0_sstables.len() >= trigger {
+                self.compact(column_family, Bound::Unbounded, Bound::Unbounded)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flush the memtables, then merge every table holding keys from `lower` to `upper` into L1,
+    /// the bottom level, dropping tombstones, overwritten values and expired entries. Returns
+    /// once the compaction is done.
+    pub fn compact_range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<()> {
+        self.compact_range_cf(DEFAULT_COLUMN_FAMILY, lower, upper)
+    }
+
+    /// Compact the keys from `lower` to `upper` of the given column family, like `compact_range`.
+    pub fn compact_range_cf(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<()> {
+        let column_family = self.column_family(column_family)?;
+        self.sync()?;
         let _flush_lock = self.flush_lock.lock();
+        self.compact(&column_family, lower, upper)
+    }
+
+    /// Flush the memtables, then merge all tables of every column family into L1. Returns once
+    /// the compaction is done.
+    pub fn force_full_compaction(&self) -> Result<()> {
+        self.sync()?;
+        let _flush_lock = self.flush_lock.lock();
+        let column_families: Vec<_> = self.column_families.read().values().cloned().collect();
+        for column_family in &column_families {
+            self.compact(column_family, Bound::Unbounded, Bound::Unbounded)?;
+        }
+        Ok(())
+    }
+
+    /// Allocate the ID of a new SST or blob file.
+    fn next_sst_id(&self) -> usize {
+        self.next_sst_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Delete the files of tables written by a flush or compaction that failed, which would
+    /// otherwise be reopened by recovery. Errors are ignored, as the operation has failed already.
+    fn delete_unpublished_tables(&self, column_family: &str, tables: &[Arc<SsTable>]) {
+        for table in tables {
+            let path = self.path_of_sst(column_family, table.sst_id());
+            let _ = self.options.backend.delete(&path);
+        }
+    }
+
+    /// Flush the immutable memtables of `column_families`, from earliest to latest, as one group
+    /// that a crash keeps either all or none of, so that it never splits a write batch across
+    /// column families. A memtable is only removed from the list once the group is committed, so
+    /// the ones left behind by a failed sync are flushed by the next one.
+    fn flush_imm_memtables(&self, column_families: &[Arc<ColumnFamily>]) -> Result<()> {
+        self.discard_abandoned_flush()?;
+        // At this point, the old memtables should be disabled for write, and all write threads
+        // should be operating on the new memtables.
+        let mut memtables = Vec::new();
+        for column_family in column_families {
+            for memtable in &column_family.snapshot().imm_memtables {
+                memtables.push((column_family.as_ref(), memtable.clone()));
+            }
+        }
+        if memtables.is_empty() {
+            return Ok(());
+        }
 
-        let flush_memtable;
-        let sst_id;
+        // The marker is named after the first ID of the flush. IDs are only allocated with the
+        // flush lock held, so every file of the flush gets that ID or a higher one.
+        let marker_id = self.next_sst_id.load(Ordering::SeqCst);
+        let flushed = self.begin_flush(marker_id).and_then(|()| {
+            let mut flushed = Vec::with_capacity(memtables.len());
+            for (column_family, memtable) in &memtables {
+                flushed.push(self.write_imm_memtable(column_family, memtable)?);
+            }
+            self.commit_flush(marker_id)?;
+            Ok(flushed)
+        });
+        let flushed = match flushed {
+            Ok(flushed) => flushed,
+            Err(err) => {
+                // Nothing was published, so the files written can all go, now if possible.
+                *self.abandoned_flush.lock() = Some(marker_id);
+                let _ = self.discard_abandoned_flush();
+                return Err(err);
+            }
+        };
 
-        // Move mutable memtable to immutable memtables.
+        for FlushedMemtable {
+            column_family,
+            ssts,
+            blob_file,
+            mut info,
+            start,
+        } in flushed
         {
-            let mut guard = self.inner.write();
-            // Swap the current memtable with a new one.
-            let mut snapshot = guard.as_ref().clone();
-            let memtable = std::mem::replace(&mut snapshot.memtable, Arc::new(MemTable::create()));
-            flush_memtable = memtable.clone();
-            sst_id = snapshot.next_sst_id;
-            // Add the memtable to the immutable memtables.
-            snapshot.imm_memtables.push(memtable);
-            // Update the snapshot.
-            *guard = Arc::new(snapshot);
-        }
-
-        // At this point, the old memtable should be disabled for write, and all write threads
-        // should be operating on the new memtable. We can safely flush the old memtable to
-        // disk.
-
-        let mut builder = SsTableBuilder::new(4096);
-        flush_memtable.flush(&mut builder)?;
-        let sst = Arc::new(builder.build(
+            // Add the flushed L0 table to the list.
+            {
+                let mut guard = column_family.inner.write();
+                let mut snapshot = guard.as_ref().clone();
+                // Remove the memtable from the immutable memtables.
+                snapshot.imm_memtables.remove(0);
+                // Add the L0 tables. They do not overlap, so their order does not matter.
+                snapshot.l0_sstables.extend(ssts.iter().cloned());
+                if let Some(blob_file) = blob_file {
+                    Arc::make_mut(&mut snapshot.blob_files)
+                        .insert(info.sst_id, Arc::new(blob_file));
+                }
+                // Update the snapshot.
+                *guard = Arc::new(snapshot);
+            }
+
+            for sst in &ssts {
+                info.tables
+                    .push(self.table_file_info(column_family, sst, 0)?);
+            }
+            for table in &info.tables {
+                self.notify(|listener| listener.on_table_file_created(table));
+            }
+            info.duration = start.elapsed();
+            self.notify(|listener| listener.on_flush_completed(&info));
+        }
+        Ok(())
+    }
+
+    /// Write an immutable memtable to durable L0 tables, without publishing them.
+    fn write_imm_memtable<'a>(
+        &self,
+        column_family: &'a ColumnFamily,
+        memtable: &MemTable,
+    ) -> Result<FlushedMemtable<'a>> {
+        let _timer = self.start_timer(Operation::Flush);
+        let start = Instant::now();
+        let sst_id = self.next_sst_id();
+        enter_span!(
+            "flush",
+            column_family = %column_family.name,
             sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
+            memtable_bytes = memtable.approximate_size()
+        );
+        let info = FlushJobInfo {
+            column_family: column_family.name.clone(),
+            sst_id,
+            memtable_bytes: memtable.approximate_size() as u64,
+            tables: Vec::new(),
+            duration: Duration::ZERO,
+        };
+        self.notify(|listener| listener.on_flush_begin(&info));
+
+        let mut writer = TableWriter::new(self, &column_family.name, sst_id, 0, IoPriority::High);
+        // The blob file, if any, is durable before the SSTs pointing into it.
+        let blob_file = self.flush_memtable(column_family, memtable, &mut writer, sst_id)?;
+        let ssts = writer.finish()?;
+        let blob_size = blob_file.as_ref().map_or(0, FileObject::size);
+        let sst_size = ssts.iter().map(|sst| sst.table_size()).sum::<u64>();
+        StatsCounters::add(
+            &self.stats_counters.flush_bytes_written,
+            sst_size + blob_size,
+        );
+        Ok(FlushedMemtable {
+            column_family,
+            ssts,
+            blob_file,
+            info,
+            start,
+        })
+    }
+
+    /// Merge the tables holding keys from `lower` to `upper`, picked by
+    /// `pick_compaction_inputs`, into new L1 tables. As L1 is the bottom level, overwritten
+    /// values, tombstones and expired entries are all dropped. Must be called with the flush lock
+    /// held, so that no table is added to L0 meanwhile.
+    fn compact(
+        &self,
+        column_family: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<()> {
+        self.discard_abandoned_flush()?;
+        let snapshot = column_family.snapshot();
+        let l1_sstables = snapshot.levels.concat();
+        let CompactionInputs {
+            l0_sstables: l0_inputs,
+            l1_sstables: l1_inputs,
+        } = pick_compaction_inputs(&snapshot.l0_sstables, &l1_sstables, lower, upper)?;
+        if l0_inputs.is_empty() && l1_inputs.is_empty() {
+            return Ok(());
+        }
+        let _timer = self.start_timer(Operation::Compaction);
+        enter_span!("compaction", column_family = %column_family.name);
+        let start = Instant::now();
+        let mut input_tables = Vec::new();
+        for table in &l0_inputs {
+            input_tables.push(self.table_file_info(column_family, table, 0)?);
+        }
+        for table in &l1_inputs {
+            input_tables.push(self.table_file_info(column_family, table, 1)?);
+        }
+        let mut info = CompactionJobInfo {
+            column_family: column_family.name.clone(),
+            input_tables,
+            output_level: 1,
+            output_tables: Vec::new(),
+            duration: Duration::ZERO,
+        };
+        self.notify(|listener| listener.on_compaction_begin(&info));
+        // Newest first, so that the merge keeps the latest version of each key.
+        let tables = l0_inputs.iter().rev();
+        let tables = tables.chain(&l1_inputs).cloned().collect::<Vec<_>>();
+        let context = CompactionContext {
+            level: 1,
+            bottom_level: true,
+            now: self.options.clock.now_millis(),
+            filter: self.options.compaction_filter.as_deref(),
+            blob_files: &snapshot.blob_files,
+        };
+        let boundaries = subcompaction_boundaries(&tables, self.options.max_subcompactions);
+        let starts = std::iter::once(None).chain(boundaries.iter().map(|key| Some(&key[..])));
+        let ends = boundaries.iter().map(|key| Some(&key[..])).chain([None]);
+        let ranges = starts
+            .zip(ends)
+            .map(|(start, end)| (start, end, self.next_sst_id()))
+            .collect::<Vec<_>>();
+        let results = if let [(start, end, sst_id)] = ranges[..] {
+            vec![self.subcompact(column_family, &tables, start, end, sst_id, &context)]
+        } else {
+            std::thread::scope(|scope| {
+                let handles = ranges
+                    .iter()
+                    .map(|&(start, end, sst_id)| {
+                        let (tables, context) = (&tables, &context);
+                        scope.spawn(move || {
+                            self.subcompact(column_family, tables, start, end, sst_id, context)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .unwrap_or_else(|_| Err(anyhow!("subcompaction panicked")))
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+        // Ranges where everything was dropped leave no SST. A failed subcompaction deletes the
+        // SSTs it wrote, and those of the others are deleted here.
+        let mut outputs = Vec::new();
+        let mut error = None;
+        for result in results {
+            match result {
+                Ok(ssts) => outputs.extend(ssts),
+                Err(err) => {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        if let Some(err) = error {
+            self.delete_unpublished_tables(&column_family.name, &outputs);
+            return Err(err);
+        }
+        let bytes_read = tables.iter().map(|table| table.table_size()).sum();
+        let bytes_written = outputs.iter().map(|sst| sst.table_size()).sum();
+        StatsCounters::add(&self.stats_counters.compaction_bytes_read, bytes_read);
+        StatsCounters::add(&self.stats_counters.compaction_bytes_written, bytes_written);
 
-        // Add the flushed L0 table to the list.
         {
-            let mut guard = self.inner.write();
-            let mut snapshot = guard.as_ref().clone();
-            // Remove the memtable from the immutable memtables.
-            snapshot.imm_memtables.pop();
-            // Add L0 table
-            snapshot.l0_sstables.push(sst);
-            // Update SST ID
-            snapshot.next_sst_id += 1;
-            // Update the snapshot.
-            *guard = Arc::new(snapshot);
+            let mut guard = column_family.inner.write();
+            let mut new_snapshot = guard.as_ref().clone();
+            new_snapshot.l0_sstables.drain(..l0_inputs.len());
+            let mut l1_sstables = new_snapshot.levels.concat();
+            l1_sstables.retain(|table| {
+                let sst_id = table.sst_id();
+                !l1_inputs.iter().any(|input| input.sst_id() == sst_id)
+            });
+            l1_sstables.extend(outputs.iter().cloned());
+            l1_sstables.sort_by(|a, b| a.first_key().cmp(b.first_key()));
+            new_snapshot.levels = vec![l1_sstables];
+            *guard = Arc::new(new_snapshot);
+        }
+
+        for sst in &outputs {
+            info.output_tables
+                .push(self.table_file_info(column_family, sst, 1)?);
         }
 
+        for table in &info.output_tables {
+            self.notify(|listener| listener.on_table_file_created(table));
+        }
+        // Readers holding an older snapshot keep the files open. The oldest tables are deleted
+        // first, so that a crash meanwhile leaves no older entry of a key without the newer ones.
+        info.input_tables.sort_by_key(|table| table.sst_id);
+        for table in &info.input_tables {
+            self.options
+                .backend
+                .delete(&self.path_of_sst(&column_family.name, table.sst_id))?;
+            self.notify(|listener| listener.on_table_file_deleted(table));
+        }
+        info.duration = start.elapsed();
+        self.notify(|listener| listener.on_compaction_completed(&info));
+        Ok(())
+    }
+
+    /// Compact the keys of `tables` from `start` up to `end` into SSTs starting with `sst_id`.
+    /// Returns no SST if no entry is left to write.
+    fn subcompact(
+        &self,
+        column_family: &ColumnFamily,
+        tables: &[Arc<SsTable>],
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        sst_id: usize,
+        context: &CompactionContext,
+    ) -> Result<Vec<Arc<SsTable>>> {
+        let mut iters = Vec::with_capacity(tables.len());
+        for table in tables {
+            let iter = match start {
+                Some(start) => SsTableIterator::create_and_seek_to_key(table.clone(), start)?,
+                None => SsTableIterator::create_and_seek_to_first(table.clone())?,
+            };
+            iters.push(Box::new(iter));
+        }
+        let iter = MergeIterator::create(iters);
+
+        let mut writer = TableWriter::new(
+            self,
+            &column_family.name,
+            sst_id,
+            context.level,
+            IoPriority::Low,
+        );
+        compact_entries(iter, end, &mut writer, context)?;
+        writer.finish()
+    }
+
+    /// Add the entries of `memtable` to `writer`, moving large values to the blob file
+    /// `blob_id`. Returns the blob file if any value was moved.
+    fn flush_memtable(
+        &self,
+        column_family: &ColumnFamily,
+        memtable: &MemTable,
+        writer: &mut TableWriter,
+        blob_id: usize,
+    ) -> Result<Option<FileObject>> {
+        let mut blob_builder = None;
+        let mut iter = memtable.scan(Bound::Unbounded, Bound::Unbounded);
+        while iter.is_valid() {
+            if self.options.is_separated(iter.value().len()) {
+                let blob_builder = match &mut blob_builder {
+                    Some(blob_builder) => blob_builder,
+                    None => {
+                        let mut new_builder = BlobFileBuilder::create(
+                            self.options.backend.clone(),
+                            blob_id,
+                            &self.path_of_blob(&column_family.name, blob_id),
+                        )?;
+                        new_builder.set_rate_limiter(self.rate_limiter.clone(), IoPriority::High);
+                        blob_builder.insert(new_builder)
+                    }
+                };
+                let pointer = blob_builder.add(iter.key(), iter.value())?;
+                let meta = EntryMeta {
+                    is_blob_pointer: true,
+                    ..iter.meta()
+                };
+                writer.add_entry(iter.key(), &pointer.encode(), meta)?;
+            } else {
+                writer.add_entry(iter.key(), iter.value(), iter.meta())?;
+            }
+            iter.next()?;
+        }
+        blob_builder.map(BlobFileBuilder::finish).transpose()
+    }
+
+    /// Rewrite the blob files in which at least `blob_gc_garbage_ratio` of the bytes belong to
+    /// overwritten or deleted values. The live values are copied to a new blob file, a new L0 SST
+    /// points their keys to the new copies, and the old blob file is deleted.
+    pub fn gc_blob_files(&self) -> Result<()> {
+        // No SST can be flushed while the pointers are being updated.
+        let _flush_lock = self.flush_lock.lock();
+        self.discard_abandoned_flush()?;
+        let column_families: Vec<_> = self.column_families.read().values().cloned().collect();
+        for column_family in &column_families {
+            self.gc_column_family_blob_files(column_family)?;
+        }
+        Ok(())
+    }
+
+    fn gc_column_family_blob_files(&self, column_family: &ColumnFamily) -> Result<()> {
+        let blob_files = column_family.inner.read().blob_files.clone();
+        let now = self.options.clock.now_millis();
+        for (&file_id, file) in blob_files.iter() {
+            enter_span!("gc_blob_file", column_family = %column_family.name, file_id);
+            let snapshot = column_family.snapshot();
+            let records = read_blob_records(file_id, file)?;
+            let total_bytes: usize = records.iter().map(|record| record.value.len()).sum();
+            let mut live = Vec::new();
+            for record in records {
+                // A value is live if the latest entry of its key still points to it.
+                if let Some((pointer, meta)) =
+                    Self::get_entry(&snapshot, &record.key, &ReadOptions::default())?
+                {
+                    if meta.is_blob_pointer
+                        && !meta.is_expired(now)
+                        && pointer == record.pointer.encode()
+                    {
+                        live.push((record, meta));
+                    }
+                }
+            }
+            let live_bytes: usize = live.iter().map(|(record, _)| record.value.len()).sum();
+            let garbage_bytes = total_bytes - live_bytes;
+            if total_bytes > 0
+                && (garbage_bytes as f64) < total_bytes as f64 * self.options.blob_gc_garbage_ratio
+            {
+                continue;
+            }
+
+            StatsCounters::add(&self.stats_counters.compaction_bytes_read, file.size());
+            let mut sst = None;
+            let mut table_info = None;
+            let mut new_blob_file = None;
+            let new_id = self.next_sst_id();
+            if !live.is_empty() {
+                let mut blob_builder = BlobFileBuilder::create(
+                    self.options.backend.clone(),
+                    new_id,
+                    &self.path_of_blob(&column_family.name, new_id),
+                )?;
+                blob_builder.set_rate_limiter(self.rate_limiter.clone(), IoPriority::Low);
+                let mut builder = SsTableBuilder::create(
+                    4096,
+                    self.options.backend.clone(),
+                    self.path_of_sst(&column_family.name, new_id),
+                )?;
+                builder.set_compression(self.options.compression_of_level(0));
+                builder.set_rate_limiter(self.rate_limiter.clone(), IoPriority::Low);
+                // Records are in key order, as they were written from a memtable or an SST.
+                for (record, meta) in &live {
+                    let pointer = blob_builder.add(&record.key, &record.value)?;
+                    builder.add_entry(&record.key, &pointer.encode(), *meta);
+                }
+                let blob_file = blob_builder.finish()?;
+                let table = builder.build(
+                    new_id,
+                    Some(self.block_cache.clone()),
+                    self.path_of_sst(&column_family.name, new_id),
+                )?;
+                StatsCounters::add(
+                    &self.stats_counters.compaction_bytes_written,
+                    blob_file.size() + table.table_size(),
+                );
+                table_info = Some(self.table_file_info(column_family, &table, 0)?);
+                new_blob_file = Some(blob_file);
+                sst = Some(Arc::new(table));
+            }
+
+            {
+                let mut guard = column_family.inner.write();
+                let mut snapshot = guard.as_ref().clone();
+                let blob_files = Arc::make_mut(&mut snapshot.blob_files);
+                blob_files.remove(&file_id);
+                if let (Some(sst), Some(new_blob_file)) = (sst, new_blob_file) {
+                    // The new pointers override the old ones as the latest L0 table.
+                    snapshot.l0_sstables.push(sst);
+                    blob_files.insert(new_id, Arc::new(new_blob_file));
+                }
+                *guard = Arc::new(snapshot);
+            }
+            if let Some(table_info) = &table_info {
+                self.notify(|listener| listener.on_table_file_created(table_info));
+            }
+            // Readers holding an older snapshot keep the file open.
+            self.options
+                .backend
+                .delete(&self.path_of_blob(&column_family.name, file_id))?;
+        }
         Ok(())
     }
 
@@ -185,34 +1147,85 @@ impl LsmStorage {
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
     ) -> Result<FusedIterator<LsmIterator>> {
-        let snapshot = {
-            let guard = self.inner.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+        self.scan_cf(DEFAULT_COLUMN_FAMILY, lower, upper)
+    }
 
-        let mut memtable_iters = Vec::new();
-        memtable_iters.reserve(snapshot.imm_memtables.len() + 1);
+    /// Create an iterator over a range of keys of the given column family.
+    pub fn scan_cf(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_cf_with_options(column_family, lower, upper, &ReadOptions::default())
+    }
+
+    /// Create an iterator over a range of keys with the given read options.
+    pub fn scan_with_options(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.scan_cf_with_options(DEFAULT_COLUMN_FAMILY, lower, upper, options)
+    }
+
+    /// Create an iterator over a range of keys of the given column family with the given read
+    /// options. The perf context of `options` also counts the reads done while iterating.
+    pub fn scan_cf_with_options(
+        &self,
+        column_family: &str,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        let _timer = self.start_timer(Operation::Scan);
+        enter_span!("scan", column_family);
+        let snapshot = self.column_family(column_family)?.snapshot();
+
+        let mut memtable_iters = Vec::with_capacity(snapshot.imm_memtables.len() + 1);
         memtable_iters.push(Box::new(snapshot.memtable.scan(lower, upper)));
         for memtable in snapshot.imm_memtables.iter().rev() {
             memtable_iters.push(Box::new(memtable.scan(lower, upper)));
         }
+        options.count(|perf| &perf.memtables_probed, memtable_iters.len() as u64);
         let memtable_iter = MergeIterator::create(memtable_iters);
 
-        let mut table_iters = Vec::new();
-        table_iters.reserve(snapshot.l0_sstables.len());
-        for table in snapshot.l0_sstables.iter().rev() {
+        let tables = snapshot.l0_sstables.iter().rev();
+        let level_tables = snapshot.levels.iter().map(Vec::len).sum::<usize>();
+        let mut table_iters = Vec::with_capacity(tables.len() + level_tables);
+        for table in tables.chain(snapshot.levels.iter().flatten()) {
+            let after_upper = match upper {
+                Bound::Included(key) => key < table.first_key(),
+                Bound::Excluded(key) => key <= table.first_key(),
+                Bound::Unbounded => false,
+            };
+            if after_upper {
+                options.count(|perf| &perf.sstables_skipped, 1);
+                continue;
+            }
+            options.count(|perf| &perf.sstables_checked, 1);
+            let table = table.clone();
             let iter = match lower {
-                Bound::Included(key) => {
-                    SsTableIterator::create_and_seek_to_key(table.clone(), key)?
-                }
+                Bound::Included(key) => SsTableIterator::create_and_seek_to_key_with_options(
+                    table,
+                    key,
+                    options.clone(),
+                )?,
                 Bound::Excluded(key) => {
-                    let mut iter = SsTableIterator::create_and_seek_to_key(table.clone(), key)?;
+                    let mut iter = SsTableIterator::create_and_seek_to_key_with_options(
+                        table,
+                        key,
+                        options.clone(),
+                    )?;
                     if iter.is_valid() && iter.key() == key {
                         iter.next()?;
                     }
                     iter
                 }
-                Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table.clone())?,
+                Bound::Unbounded => {
+                    SsTableIterator::create_and_seek_to_first_with_options(table, options.clone())?
+                }
             };
 
             table_iters.push(Box::new(iter));
@@ -224,6 +1237,8 @@ impl LsmStorage {
         Ok(FusedIterator::new(LsmIterator::new(
             iter,
             map_bound(upper),
+            snapshot.blob_files.clone(),
+            self.options.clock.now_millis(),
         )?))
     }
 }
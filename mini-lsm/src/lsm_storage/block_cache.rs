@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::block::Block;
+
+/// The cache of decoded blocks, keyed by SST ID and block index, which counts its hits and
+/// misses.
+pub struct BlockCache {
+    cache: moka::sync::Cache<(usize, usize), Arc<Block>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl BlockCache {
+    /// Create a cache holding up to `capacity` blocks.
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            cache: moka::sync::Cache::new(capacity),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get block `block_idx` of SST `sst_id`, reading it with `read` if it is not cached.
+    pub fn get_or_read(
+        &self,
+        sst_id: usize,
+        block_idx: usize,
+        read: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        let mut is_miss = false;
+        let block = self
+            .cache
+            .try_get_with((sst_id, block_idx), || {
+                is_miss = true;
+                read()
+            })
+            .map_err(|e| anyhow!("{}", e))?;
+        let counter = if is_miss { &self.misses } else { &self.hits };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(block)
+    }
+
+    /// Get block `block_idx` of SST `sst_id` if it is cached.
+    pub fn get(&self, sst_id: usize, block_idx: usize) -> Option<Arc<Block>> {
+        let block = self.cache.get(&(sst_id, block_idx));
+        let counter = if block.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
+    }
+
+    /// Get the number of lookups served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of lookups that had to read the block.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
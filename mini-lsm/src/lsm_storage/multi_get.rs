@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+
+use super::{LsmStorage, LsmStorageInner, DEFAULT_COLUMN_FAMILY};
+use crate::block::{Block, BlockIterator, EntryMeta};
+use crate::metrics::Operation;
+use crate::read_options::ReadOptions;
+use crate::table::SsTable;
+use crate::trace::enter_span;
+
+impl LsmStorage {
+    /// Get several keys from the storage. The values are returned in the order of `keys`.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Result<Vec<Option<Bytes>>> {
+        self.multi_get_cf(DEFAULT_COLUMN_FAMILY, keys)
+    }
+
+    /// Get several keys from the given column family.
+    pub fn multi_get_cf(&self, column_family: &str, keys: &[&[u8]]) -> Result<Vec<Option<Bytes>>> {
+        self.multi_get_cf_with_options(column_family, keys, &ReadOptions::default())
+    }
+
+    /// Get several keys from the storage with the given read options.
+    pub fn multi_get_with_options(
+        &self,
+        keys: &[&[u8]],
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<Bytes>>> {
+        self.multi_get_cf_with_options(DEFAULT_COLUMN_FAMILY, keys, options)
+    }
+
+    /// Get several keys from the given column family with the given read options. All keys are
+    /// read from the same snapshot, which holds either all or none of the updates of each write
+    /// batch, and each block holding some of them is read once. With
+    /// `options.multi_get_threads` above 1, the sorted keys are split into ranges looked up on
+    /// separate threads.
+    pub fn multi_get_cf_with_options(
+        &self,
+        column_family: &str,
+        keys: &[&[u8]],
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<Bytes>>> {
+        let _timer = self.start_timer(Operation::MultiGet);
+        enter_span!("multi_get", column_family, keys = keys.len());
+        let column_family = self.column_family(column_family)?;
+
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&idx| keys[idx]);
+        let sorted_keys = order.iter().map(|&idx| keys[idx]).collect::<Vec<_>>();
+        // The memtables are searched between write batches. The SSTs only change when memtables
+        // are frozen, which also waits for write batches.
+        let (snapshot, memtable_entries) = {
+            let _batch_lock = self.batch_lock.read();
+            let snapshot = column_family.snapshot();
+            let entries = sorted_keys
+                .iter()
+                .map(|key| Self::get_memtable_entry(&snapshot, key, options))
+                .collect::<Vec<_>>();
+            (snapshot, entries)
+        };
+        let threads = options.multi_get_threads.clamp(1, sorted_keys.len().max(1));
+        let entries = if threads == 1 {
+            Self::get_sorted_entries(&snapshot, &sorted_keys, memtable_entries, options)?
+        } else {
+            let chunk_size = (sorted_keys.len() - 1) / threads + 1;
+            std::thread::scope(|scope| {
+                let handles = sorted_keys
+                    .chunks(chunk_size)
+                    .zip(memtable_entries.chunks(chunk_size))
+                    .map(|(chunk, chunk_entries)| {
+                        let snapshot = &snapshot;
+                        let chunk_entries = chunk_entries.to_vec();
+                        scope.spawn(move || {
+                            Self::get_sorted_entries(snapshot, chunk, chunk_entries, options)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let mut entries = Vec::with_capacity(sorted_keys.len());
+                for handle in handles {
+                    let chunk_entries = handle
+                        .join()
+                        .map_err(|_| anyhow!("multi_get thread panicked"))??;
+                    entries.extend(chunk_entries);
+                }
+                Ok::<_, anyhow::Error>(entries)
+            })?
+        };
+
+        let now = self.options.clock.now_millis();
+        let mut values = vec![None; keys.len()];
+        for (idx, entry) in order.into_iter().zip(entries) {
+            values[idx] = Self::resolve_entry(&snapshot, entry, now)?;
+        }
+        Ok(values)
+    }
+
+    /// Find the latest entries of sorted `keys`, given the `entries` found in the memtables. The
+    /// SSTs are searched from the newest, and each one only for the keys not found yet, grouped
+    /// by the block that may hold them. Tables of the lower levels do not overlap, so each key is
+    /// only searched in the one table of a level that may hold it.
+    fn get_sorted_entries(
+        snapshot: &LsmStorageInner,
+        keys: &[&[u8]],
+        mut entries: Vec<Option<(Bytes, EntryMeta)>>,
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<(Bytes, EntryMeta)>>> {
+        let mut pending = (0..keys.len())
+            .filter(|&idx| entries[idx].is_none())
+            .collect::<Vec<_>>();
+
+        enter_span!(
+            "search_sstables",
+            l0_sstables = snapshot.l0_sstables.len(),
+            levels = snapshot.levels.len()
+        );
+        for table in snapshot.l0_sstables.iter().rev() {
+            if pending.is_empty() {
+                break;
+            }
+            if keys[*pending.last().unwrap()] < table.first_key() {
+                options.count(|perf| &perf.sstables_skipped, 1);
+                continue;
+            }
+            options.count(|perf| &perf.sstables_checked, 1);
+            pending = Self::get_table_entries(table, keys, &pending, &mut entries, options)?;
+        }
+        for level in &snapshot.levels {
+            let mut not_found = Vec::with_capacity(pending.len());
+            let mut checked = 0;
+            let mut rest = &pending[..];
+            while let Some(&idx) = rest.first() {
+                let table_idx = level.partition_point(|table| table.first_key() <= keys[idx]);
+                if table_idx == 0 {
+                    not_found.push(idx);
+                    rest = &rest[1..];
+                    continue;
+                }
+                // The keys before the first key of the next table can only be in this one.
+                let end = match level.get(table_idx) {
+                    Some(next) => rest.partition_point(|&idx| keys[idx] < next.first_key()),
+                    None => rest.len(),
+                };
+                checked += 1;
+                let table = &level[table_idx - 1];
+                let table_pending = &rest[..end];
+                not_found.extend(Self::get_table_entries(
+                    table,
+                    keys,
+                    table_pending,
+                    &mut entries,
+                    options,
+                )?);
+                rest = &rest[end..];
+            }
+            options.count(|perf| &perf.sstables_checked, checked);
+            options.count(|perf| &perf.sstables_skipped, level.len() as u64 - checked);
+            pending = not_found;
+        }
+        Ok(entries)
+    }
+
+    /// Look up the sorted `pending` keys in `table`, reading each block holding some of them
+    /// once. Returns the keys not found.
+    fn get_table_entries(
+        table: &SsTable,
+        keys: &[&[u8]],
+        pending: &[usize],
+        entries: &mut [Option<(Bytes, EntryMeta)>],
+        options: &ReadOptions,
+    ) -> Result<Vec<usize>> {
+        let mut block: Option<(usize, Arc<Block>)> = None;
+        let mut not_found = Vec::with_capacity(pending.len());
+        for &idx in pending {
+            let key = keys[idx];
+            if key < table.first_key() {
+                not_found.push(idx);
+                continue;
+            }
+            let block_idx = table.find_block_idx(key);
+            let block = match &block {
+                Some((cached_idx, block)) if *cached_idx == block_idx => block.clone(),
+                _ => {
+                    let read = table.read_block_cached_with_options(block_idx, options)?;
+                    block = Some((block_idx, read.clone()));
+                    read
+                }
+            };
+            let iter = BlockIterator::create_and_seek_to_key(block, key);
+            if iter.is_valid() && iter.key() == key {
+                entries[idx] = Some((iter.value_bytes(), iter.meta()));
+            } else {
+                not_found.push(idx);
+            }
+        }
+        Ok(not_found)
+    }
+}
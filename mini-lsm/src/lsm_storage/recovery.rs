@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+
+use super::{ColumnFamily, LsmStorage, LsmStorageInner, DEFAULT_COLUMN_FAMILY};
+use crate::blob::BlobFiles;
+use crate::table::{FileObject, SsTable};
+
+/// Get the name of file `id` of `column_family`, with extension `ext`.
+pub(super) fn file_name(column_family: &str, id: usize, ext: &str) -> String {
+    if column_family == DEFAULT_COLUMN_FAMILY {
+        format!("{:05}.{}", id, ext)
+    } else {
+        format!("{}.{:05}.{}", column_family, id, ext)
+    }
+}
+
+/// Get the column family, ID and extension of a file named by `file_name`.
+fn parse_file_name(path: &Path) -> Option<(&str, usize, &str)> {
+    let stem = path.file_stem()?.to_str()?;
+    let ext = path.extension()?.to_str()?;
+    let (column_family, id) = match stem.rsplit_once('.') {
+        Some((column_family, id)) => (column_family, id),
+        None => (DEFAULT_COLUMN_FAMILY, stem),
+    };
+    Some((column_family, id.parse().ok()?, ext))
+}
+
+/// Check that `name` can prefix the names of the files of a column family.
+pub(super) fn check_column_family_name(name: &str) -> Result<()> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(valid) {
+        bail!(
+            "invalid column family name {:?}: use ASCII letters, digits, '_' and '-'",
+            name
+        );
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct ColumnFamilyFiles {
+    ssts: BTreeMap<usize, PathBuf>,
+    blobs: BTreeMap<usize, PathBuf>,
+}
+
+impl LsmStorage {
+    /// Reopen the SSTs and blob files left in the directory of the storage, and delete the
+    /// temporary files of writes interrupted by a crash. There is no manifest yet, so the level
+    /// of each table is unknown and all of them are reopened into L0, ordered by ID. This keeps
+    /// the latest entry of each key in front, as a compaction writes tables with newer IDs than
+    /// the ones it merges, and deletes those from the oldest. Column families are recovered from
+    /// the names of their files, so one without any file is not. The files of a flush that was
+    /// not committed are deleted first.
+    pub(super) fn recover(&self) -> Result<()> {
+        let backend = &self.options.backend;
+        let marker_id = backend
+            .list(&self.path)?
+            .iter()
+            .filter_map(|path| match parse_file_name(path) {
+                Some((_, id, "flush")) => Some(id),
+                _ => None,
+            })
+            .min();
+        if let Some(marker_id) = marker_id {
+            self.discard_flush(marker_id)?;
+        }
+
+        let mut column_families = BTreeMap::<String, ColumnFamilyFiles>::new();
+        let mut max_id = 0;
+        for path in backend.list(&self.path)? {
+            if path.extension().and_then(|ext| ext.to_str()) == Some("tmp") {
+                backend.delete(&path)?;
+                continue;
+            }
+            let Some((column_family, id, ext)) = parse_file_name(&path) else {
+                continue;
+            };
+            let files = column_families
+                .entry(column_family.to_string())
+                .or_default();
+            match ext {
+                "sst" => files.ssts.insert(id, path.clone()),
+                "blob" => files.blobs.insert(id, path.clone()),
+                _ => continue,
+            };
+            max_id = max_id.max(id);
+        }
+        self.next_sst_id.fetch_max(max_id + 1, Ordering::SeqCst);
+
+        let mut guard = self.column_families.write();
+        for (name, files) in column_families {
+            let mut snapshot = LsmStorageInner::create();
+            for (id, path) in files.ssts {
+                let file = FileObject::open_with_backend(backend.as_ref(), &path)?;
+                let table = SsTable::open(id, Some(self.block_cache.clone()), file)?;
+                snapshot.l0_sstables.push(Arc::new(table));
+            }
+            let mut blob_files = BlobFiles::new();
+            for (id, path) in files.blobs {
+                let file = FileObject::open_with_backend(backend.as_ref(), &path)?;
+                blob_files.insert(id, Arc::new(file));
+            }
+            snapshot.blob_files = Arc::new(blob_files);
+            let column_family = ColumnFamily::new(&name);
+            *column_family.inner.write() = Arc::new(snapshot);
+            guard.insert(name, Arc::new(column_family));
+        }
+        Ok(())
+    }
+
+    fn flush_marker_path(&self, marker_id: usize) -> PathBuf {
+        self.path
+            .join(file_name(DEFAULT_COLUMN_FAMILY, marker_id, "flush"))
+    }
+
+    /// Durably create the marker of a flush whose files get `marker_id` and higher IDs, before
+    /// any of them. Until the marker is deleted, recovery treats every such file as part of the
+    /// flush, and deletes it.
+    pub(super) fn begin_flush(&self, marker_id: usize) -> Result<()> {
+        let backend = self.options.backend.clone();
+        FileObject::create_with_backend(backend, &self.flush_marker_path(marker_id), Vec::new())?;
+        Ok(())
+    }
+
+    /// Durably delete the marker of a flush once all its files are durable, so that recovery
+    /// keeps all of them.
+    pub(super) fn commit_flush(&self, marker_id: usize) -> Result<()> {
+        let backend = &self.options.backend;
+        backend.delete(&self.flush_marker_path(marker_id))?;
+        backend.sync_dir(&self.path)
+    }
+
+    /// Delete the files of the flush with marker `marker_id`, that is every SST and blob file
+    /// with that ID or a higher one, then the marker. The marker may already be gone, if a failed flush
+    /// deleted it but could not sync the directory.
+    fn discard_flush(&self, marker_id: usize) -> Result<()> {
+        let backend = &self.options.backend;
+        let mut markers = Vec::new();
+        for path in backend.list(&self.path)? {
+            match parse_file_name(&path) {
+                Some((_, id, "sst" | "blob")) if id >= marker_id => backend.delete(&path)?,
+                Some((_, id, "flush")) if id >= marker_id => markers.push(path),
+                _ => {}
+            }
+        }
+        // The files must be gone before the marker, or a crash could leave them without it.
+        backend.sync_dir(&self.path)?;
+        for marker in markers {
+            backend.delete(&marker)?;
+        }
+        backend.sync_dir(&self.path)
+    }
+
+    /// Delete the files of a flush that failed, if any. As recovery would delete every file
+    /// written after its marker, this must succeed before another table is written.
+    pub(super) fn discard_abandoned_flush(&self) -> Result<()> {
+        let mut abandoned_flush = self.abandoned_flush.lock();
+        if let Some(marker_id) = *abandoned_flush {
+            self.discard_flush(marker_id)?;
+            *abandoned_flush = None;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{LsmStorage, LsmStorageInner, WriteStallStats};
+
+/// The number and total size of a set of files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    pub num_files: usize,
+    pub size_bytes: u64,
+}
+
+impl FileStats {
+    fn add(&mut self, size_bytes: u64) {
+        self.num_files += 1;
+        self.size_bytes += size_bytes;
+    }
+}
+
+/// A point-in-time view of the shape of the LSM tree and of what it has done since it was
+/// opened. Sizes and files are summed over all column families.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageStats {
+    /// The SSTs of each level, starting with L0.
+    pub levels: Vec<FileStats>,
+    /// The blob files holding separated values.
+    pub blob_files: FileStats,
+    /// Bytes put into the current memtables.
+    pub memtable_bytes: u64,
+    /// Number of immutable memtables waiting to be flushed.
+    pub num_imm_memtables: usize,
+    /// Bytes put into the immutable memtables.
+    pub imm_memtable_bytes: u64,
+    /// Block reads served from the block cache.
+    pub block_cache_hits: u64,
+    /// Block reads that missed the block cache.
+    pub block_cache_misses: u64,
+    /// Bytes of keys and values written by the application, including tombstones.
+    pub user_bytes_written: u64,
+    /// Bytes of SSTs and blob files written by flushes.
+    pub flush_bytes_written: u64,
+    /// Bytes of SSTs and blob files read by compactions and blob garbage collection.
+    pub compaction_bytes_read: u64,
+    /// Bytes of SSTs and blob files written by compactions and blob garbage collection.
+    pub compaction_bytes_written: u64,
+    /// Bytes written to storage per byte written by the application.
+    pub write_amplification: f64,
+    /// The most tables and memtables a point lookup may have to check in any column family.
+    pub read_amplification: usize,
+    pub write_stall: WriteStallStats,
+}
+
+#[derive(Default)]
+pub(super) struct StatsCounters {
+    pub(super) user_bytes_written: AtomicU64,
+    pub(super) flush_bytes_written: AtomicU64,
+    pub(super) compaction_bytes_read: AtomicU64,
+    pub(super) compaction_bytes_written: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(super) fn add(counter: &AtomicU64, bytes: u64) {
+        counter.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+impl LsmStorageInner {
+    /// Get the number of sorted runs a point lookup may have to check.
+    fn sorted_runs(&self) -> usize {
+        let levels = self.levels.iter().filter(|level| !level.is_empty());
+        1 + self.imm_memtables.len() + self.l0_sstables.len() + levels.count()
+    }
+}
+
+impl LsmStorage {
+    /// Get the statistics of the storage. The counters are updated as the storage works, so
+    /// this only walks the current list of tables.
+    pub fn stats(&self) -> StorageStats {
+        let counters = &self.stats_counters;
+        let mut stats = StorageStats {
+            block_cache_hits: self.block_cache.hits(),
+            block_cache_misses: self.block_cache.misses(),
+            user_bytes_written: counters.user_bytes_written.load(Ordering::Relaxed),
+            flush_bytes_written: counters.flush_bytes_written.load(Ordering::Relaxed),
+            compaction_bytes_read: counters.compaction_bytes_read.load(Ordering::Relaxed),
+            compaction_bytes_written: counters.compaction_bytes_written.load(Ordering::Relaxed),
+            write_stall: self.write_stall_stats(),
+            ..Default::default()
+        };
+        for column_family in self.column_families.read().values() {
+            let snapshot = column_family.snapshot();
+            let levels = std::iter::once(&snapshot.l0_sstables).chain(snapshot.levels.iter());
+            for (level, tables) in levels.enumerate() {
+                if stats.levels.len() <= level {
+                    stats.levels.resize(level + 1, FileStats::default());
+                }
+                for table in tables {
+                    stats.levels[level].add(table.table_size());
+                }
+            }
+            for file in snapshot.blob_files.values() {
+                stats.blob_files.add(file.size());
+            }
+            stats.memtable_bytes += snapshot.memtable.approximate_size() as u64;
+            stats.num_imm_memtables += snapshot.imm_memtables.len();
+            stats.imm_memtable_bytes += snapshot
+                .imm_memtables
+                .iter()
+                .map(|memtable| memtable.approximate_size() as u64)
+                .sum::<u64>();
+            stats.read_amplification = stats.read_amplification.max(snapshot.sorted_runs());
+        }
+        if stats.user_bytes_written > 0 {
+            let written = stats.flush_bytes_written + stats.compaction_bytes_written;
+            stats.write_amplification = written as f64 / stats.user_bytes_written as f64;
+        }
+        stats
+    }
+}
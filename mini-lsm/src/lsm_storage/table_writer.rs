@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::LsmStorage;
+use crate::block::EntryMeta;
+use crate::rate_limiter::IoPriority;
+use crate::table::{SsTable, SsTableBuilder};
+
+/// Writes entries in key order into SSTs of `level` of a column family, moving on to a new SST
+/// once the current one reaches `LsmStorageOptions::target_file_size`. Every key lands in a single
+/// SST. The SSTs already written are deleted if the writer is dropped before `finish` returns
+/// them, so that a failed flush or compaction leaves none for recovery to reopen.
+pub(crate) struct TableWriter<'a> {
+    storage: &'a LsmStorage,
+    column_family: &'a str,
+    level: usize,
+    priority: IoPriority,
+    /// ID of the next SST, allocated up front for the first one.
+    next_sst_id: Option<usize>,
+    builder: Option<(usize, SsTableBuilder)>,
+    last_key: Vec<u8>,
+    tables: Vec<Arc<SsTable>>,
+}
+
+impl<'a> TableWriter<'a> {
+    /// Create a writer whose first SST is `sst_id`. The next ones get new IDs.
+    pub(crate) fn new(
+        storage: &'a LsmStorage,
+        column_family: &'a str,
+        sst_id: usize,
+        level: usize,
+        priority: IoPriority,
+    ) -> Self {
+        Self {
+            storage,
+            column_family,
+            level,
+            priority,
+            next_sst_id: Some(sst_id),
+            builder: None,
+            last_key: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+
+    /// Add an entry after all the entries added so far.
+    pub(crate) fn add_entry(&mut self, key: &[u8], value: &[u8], meta: EntryMeta) -> Result<()> {
+        let target_file_size = self.storage.options.target_file_size;
+        if let Some((_, builder)) = &self.builder {
+            if target_file_size > 0
+                && builder.estimated_size() >= target_file_size
+                && key != &self.last_key[..]
+            {
+                self.finish_table()?;
+            }
+        }
+        let (_, builder) = match &mut self.builder {
+            Some(builder) => builder,
+            None => {
+                let sst_id = match self.next_sst_id.take() {
+                    Some(sst_id) => sst_id,
+                    None => self.storage.next_sst_id(),
+                };
+                let builder = self.create_builder(sst_id)?;
+                self.builder.insert((sst_id, builder))
+            }
+        };
+        builder.add_entry(key, value, meta);
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        Ok(())
+    }
+
+    fn create_builder(&self, sst_id: usize) -> Result<SsTableBuilder> {
+        let options = &self.storage.options;
+        let mut builder = SsTableBuilder::create(
+            4096,
+            options.backend.clone(),
+            self.storage.path_of_sst(self.column_family, sst_id),
+        )?;
+        builder.set_compression(options.compression_of_level(self.level));
+        builder.set_zstd_dictionary(options.zstd_dictionary_size);
+        builder.set_rate_limiter(self.storage.rate_limiter.clone(), self.priority);
+        Ok(builder)
+    }
+
+    fn finish_table(&mut self) -> Result<()> {
+        let Some((sst_id, builder)) = self.builder.take() else {
+            return Ok(());
+        };
+        let sst = builder.build(
+            sst_id,
+            Some(self.storage.block_cache.clone()),
+            self.storage.path_of_sst(self.column_family, sst_id),
+        )?;
+        self.tables.push(Arc::new(sst));
+        Ok(())
+    }
+
+    /// Finish the last SST and get all the SSTs written, in key order. No SST is written if no
+    /// entry was added.
+    pub(crate) fn finish(mut self) -> Result<Vec<Arc<SsTable>>> {
+        self.finish_table()?;
+        Ok(std::mem::take(&mut self.tables))
+    }
+}
+
+impl Drop for TableWriter<'_> {
+    fn drop(&mut self) {
+        self.storage
+            .delete_unpublished_tables(self.column_family, &self.tables);
+    }
+}
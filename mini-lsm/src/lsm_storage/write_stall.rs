@@ -0,0 +1,184 @@
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use super::{ColumnFamily, LsmStorage, LsmStorageInner};
+use crate::event_listener::WriteStallInfo;
+
+/// Why writes are delayed or stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteStallCause {
+    /// Too many tables in L0, which every read has to check.
+    Level0Files,
+    /// Too many immutable memtables waiting to be flushed.
+    ImmutableMemtables,
+    /// The compaction that is due would rewrite too many bytes.
+    PendingCompactionBytes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WriteStall {
+    Slowdown(WriteStallCause),
+    Stop(WriteStallCause),
+}
+
+/// How often and for how long writes have been delayed or stopped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteStallStats {
+    /// Writes delayed by a slowdown trigger.
+    pub slowdowns: u64,
+    /// Writes stopped by a stop trigger until they flushed or compacted the tables themselves.
+    pub stops: u64,
+    /// Total time writes spent delayed or blocked, measured on the clock of the storage.
+    pub stall_duration: Duration,
+}
+
+#[derive(Default)]
+pub(super) struct WriteStallCounters {
+    slowdowns: AtomicU64,
+    stops: AtomicU64,
+    stall_millis: AtomicU64,
+}
+
+impl LsmStorageInner {
+    /// Get the number of bytes the compaction that is due would rewrite.
+    fn pending_compaction_bytes(&self, compaction_trigger: usize) -> u64 {
+        if compaction_trigger == 0 || self.l0_sstables.len() < compaction_trigger {
+            return 0;
+        }
+        let tables = self.l0_sstables.iter();
+        tables
+            .chain(self.levels.iter().flatten())
+            .map(|table| table.table_size())
+            .sum()
+    }
+}
+
+impl LsmStorage {
+    fn write_stall(&self, snapshot: &LsmStorageInner) -> Option<WriteStall> {
+        let options = &self.options;
+        let exceeds = |value: u64, limit: u64| limit > 0 && value >= limit;
+        let l0_files = snapshot.l0_sstables.len() as u64;
+        let imm_memtables = snapshot.imm_memtables.len() as u64;
+        let pending_bytes = snapshot.pending_compaction_bytes(options.level0_compaction_trigger);
+        if exceeds(l0_files, options.level0_stop_writes_trigger as u64) {
+            Some(WriteStall::Stop(WriteStallCause::Level0Files))
+        } else if exceeds(imm_memtables, options.max_imm_memtables as u64) {
+            Some(WriteStall::Stop(WriteStallCause::ImmutableMemtables))
+        } else if exceeds(pending_bytes, options.hard_pending_compaction_bytes_limit) {
+            Some(WriteStall::Stop(WriteStallCause::PendingCompactionBytes))
+        } else if exceeds(l0_files, options.level0_slowdown_writes_trigger as u64) {
+            Some(WriteStall::Slowdown(WriteStallCause::Level0Files))
+        } else if exceeds(pending_bytes, options.soft_pending_compaction_bytes_limit) {
+            Some(WriteStall::Slowdown(
+                WriteStallCause::PendingCompactionBytes,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Delay the calling writer if any of `column_families` is past a slowdown trigger. If one is
+    /// past a stop trigger, the writer flushes or compacts it itself, as nothing else would, and
+    /// fails if that fails or leaves the column family past the trigger.
+    pub(super) fn stall_writes(&self, column_families: &[Arc<ColumnFamily>]) -> Result<()> {
+        let start = self.options.clock.now_millis();
+        let mut stopped = false;
+        let mut slowdown = None;
+        for column_family in column_families {
+            match self.write_stall(&column_family.snapshot()) {
+                Some(WriteStall::Stop(cause)) => {
+                    if !stopped {
+                        stopped = true;
+                        self.write_stall_counters
+                            .stops
+                            .fetch_add(1, Ordering::Relaxed);
+                        let info = WriteStallInfo {
+                            cause,
+                            stopped: true,
+                        };
+                        self.notify(|listener| listener.on_write_stall(&info));
+                    }
+                    self.resolve_write_stop(column_family, cause)?;
+                }
+                Some(WriteStall::Slowdown(cause)) => {
+                    slowdown = slowdown.or(Some(cause));
+                }
+                None => {}
+            }
+        }
+        let slowdown = match slowdown {
+            Some(cause) if !stopped => {
+                let info = WriteStallInfo {
+                    cause,
+                    stopped: false,
+                };
+                self.notify(|listener| listener.on_write_stall(&info));
+                true
+            }
+            _ => false,
+        };
+        if slowdown {
+            self.write_stall_counters
+                .slowdowns
+                .fetch_add(1, Ordering::Relaxed);
+            self.options.clock.sleep(self.options.write_slowdown_delay);
+        }
+        if stopped || slowdown {
+            let elapsed = self.options.clock.now_millis().saturating_sub(start);
+            self.write_stall_counters
+                .stall_millis
+                .fetch_add(elapsed, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Flush the immutable memtables of every column family if one is past a stop trigger, or
+    /// compact its L0 into L1, unless another writer or `sync` did so while the flush lock was waited for.
+    fn resolve_write_stop(
+        &self,
+        column_family: &ColumnFamily,
+        cause: WriteStallCause,
+    ) -> Result<()> {
+        let _flush_lock = self.flush_lock.lock();
+        if !matches!(
+            self.write_stall(&column_family.snapshot()),
+            Some(WriteStall::Stop(_))
+        ) {
+            return Ok(());
+        }
+        match cause {
+            WriteStallCause::ImmutableMemtables => {
+                // The memtables of all column families are flushed together, like `sync` does,
+                // so that a write batch spanning them is committed as a whole.
+                let column_families: Vec<_> =
+                    self.column_families.read().values().cloned().collect();
+                self.flush_imm_memtables(&column_families)?
+            }
+            WriteStallCause::Level0Files | WriteStallCause::PendingCompactionBytes => {
+                self.compact(column_family, Bound::Unbounded, Bound::Unbounded)?
+            }
+        }
+        if let Some(WriteStall::Stop(cause)) = self.write_stall(&column_family.snapshot()) {
+            bail!(
+                "writes to column family {} are stopped by {:?}",
+                column_family.name,
+                cause
+            );
+        }
+        Ok(())
+    }
+
+    /// Get how often and for how long writes have been delayed or stopped.
+    pub fn write_stall_stats(&self) -> WriteStallStats {
+        let counters = &self.write_stall_counters;
+        WriteStallStats {
+            slowdowns: counters.slowdowns.load(Ordering::Relaxed),
+            stops: counters.stops.load(Ordering::Relaxed),
+            stall_duration: Duration::from_millis(counters.stall_millis.load(Ordering::Relaxed)),
+        }
+    }
+}
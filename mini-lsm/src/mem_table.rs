@@ -1,4 +1,5 @@
 use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -7,12 +8,18 @@ use crossbeam_skiplist::map::Entry;
 use crossbeam_skiplist::SkipMap;
 use ouroboros::self_referencing;
 
+use crate::block::EntryMeta;
 use crate::iterators::StorageIterator;
 use crate::table::SsTableBuilder;
 
+/// A value in the mem-table, with the time it expires at in milliseconds since the Unix epoch.
+type MemTableValue = (Bytes, Option<u64>);
+
 /// A basic mem-table based on crossbeam-skiplist
 pub struct MemTable {
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<Bytes, MemTableValue>>,
+    /// The total size of the keys and values put, including overwritten ones.
+    approximate_size: AtomicUsize,
 }
 
 pub(crate) fn map_bound(bound: Bound<&[u8]>) -> Bound<Bytes> {
@@ -28,18 +35,48 @@ impl MemTable {
     pub fn create() -> Self {
         Self {
             map: Arc::new(SkipMap::new()),
+            approximate_size: AtomicUsize::new(0),
         }
     }
 
     /// Get a value by key.
     pub fn get(&self, key: &[u8]) -> Option<Bytes> {
+        self.get_with_expiry(key).map(|(value, _)| value)
+    }
+
+    /// Get a value by key, with the time it expires at.
+    pub fn get_with_expiry(&self, key: &[u8]) -> Option<(Bytes, Option<u64>)> {
         self.map.get(key).map(|e| e.value().clone())
     }
 
     /// Put a key-value pair into the mem-table.
     pub fn put(&self, key: &[u8], value: &[u8]) {
-        self.map
-            .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
+        self.insert(key, value, None);
+    }
+
+    /// Put a key-value pair into the mem-table, which expires at `expires_at` milliseconds since
+    /// the Unix epoch.
+    pub fn put_with_expiry(&self, key: &[u8], value: &[u8], expires_at: u64) {
+        self.insert(key, value, Some(expires_at));
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8], expires_at: Option<u64>) {
+        self.approximate_size
+            .fetch_add(key.len() + value.len(), Ordering::Relaxed);
+        self.map.insert(
+            Bytes::copy_from_slice(key),
+            (Bytes::copy_from_slice(value), expires_at),
+        );
+    }
+
+    /// Get the number of bytes put into the mem-table.
+    pub fn approximate_size(&self) -> usize {
+        self.approximate_size.load(Ordering::Relaxed)
+    }
+
+    /// Check if the mem-table has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
     }
 
     /// Get an iterator over a range of keys.
@@ -48,7 +85,7 @@ impl MemTable {
         let mut iter = MemTableIteratorBuilder {
             map: self.map.clone(),
             iter_builder: |map| map.range((lower, upper)),
-            item: (Bytes::from_static(&[]), Bytes::from_static(&[])),
+            item: (Bytes::from_static(&[]), (Bytes::from_static(&[]), None)),
         }
         .build();
         let entry = iter.with_iter_mut(|iter| MemTableIterator::entry_to_item(iter.next()));
@@ -59,36 +96,41 @@ impl MemTable {
     /// Flush the mem-table to SSTable.
     pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
         for entry in self.map.iter() {
-            builder.add(&entry.key()[..], &entry.value()[..]);
+            let (value, expires_at) = entry.value();
+            let meta = EntryMeta {
+                is_blob_pointer: false,
+                expires_at: *expires_at,
+            };
+            builder.add_entry(&entry.key()[..], &value[..], meta);
         }
         Ok(())
     }
 }
 
 type SkipMapRangeIter<'a> =
-    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Bytes>;
+    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, MemTableValue>;
 
 /// An iterator over a range of `SkipMap`.
 #[self_referencing]
 pub struct MemTableIterator {
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<Bytes, MemTableValue>>,
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
-    item: (Bytes, Bytes),
+    item: (Bytes, MemTableValue),
 }
 
 impl MemTableIterator {
-    fn entry_to_item(entry: Option<Entry<'_, Bytes, Bytes>>) -> (Bytes, Bytes) {
+    fn entry_to_item(entry: Option<Entry<'_, Bytes, MemTableValue>>) -> (Bytes, MemTableValue) {
         entry
             .map(|x| (x.key().clone(), x.value().clone()))
-            .unwrap_or_else(|| (Bytes::from_static(&[]), Bytes::from_static(&[])))
+            .unwrap_or_else(|| (Bytes::from_static(&[]), (Bytes::from_static(&[]), None)))
     }
 }
 
 impl StorageIterator for MemTableIterator {
     fn value(&self) -> &[u8] {
-        &self.borrow_item().1[..]
+        &self.borrow_item().1 .0[..]
     }
 
     fn key(&self) -> &[u8] {
@@ -99,6 +141,13 @@ impl StorageIterator for MemTableIterator {
         !self.borrow_item().0.is_empty()
     }
 
+    fn meta(&self) -> EntryMeta {
+        EntryMeta {
+            is_blob_pointer: false,
+            expires_at: self.borrow_item().1 .1,
+        }
+    }
+
     fn next(&mut self) -> Result<()> {
         let entry = self.with_iter_mut(|iter| MemTableIterator::entry_to_item(iter.next()));
         self.with_mut(|x| *x.item = entry);
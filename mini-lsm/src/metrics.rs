@@ -0,0 +1,287 @@
+//! Latency histograms of the storage operations and background jobs, rendered in the Prometheus
+//! text exposition format.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::lsm_storage::StorageStats;
+
+/// An operation whose latency is recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Get,
+    /// Looking up several keys with `multi_get`.
+    MultiGet,
+    Put,
+    Delete,
+    /// Applying a write batch.
+    Write,
+    /// Creating a scan iterator, not iterating over it.
+    Scan,
+    Sync,
+    /// Flushing one immutable memtable.
+    Flush,
+    Compaction,
+}
+
+impl Operation {
+    const ALL: [Operation; 9] = [
+        Operation::Get,
+        Operation::MultiGet,
+        Operation::Put,
+        Operation::Delete,
+        Operation::Write,
+        Operation::Scan,
+        Operation::Sync,
+        Operation::Flush,
+        Operation::Compaction,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Get => "get",
+            Operation::MultiGet => "multi_get",
+            Operation::Put => "put",
+            Operation::Delete => "delete",
+            Operation::Write => "write",
+            Operation::Scan => "scan",
+            Operation::Sync => "sync",
+            Operation::Flush => "flush",
+            Operation::Compaction => "compaction",
+        }
+    }
+}
+
+/// Upper bounds of the histogram buckets, in seconds.
+const BUCKETS: [f64; 14] = [
+    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0,
+];
+
+/// A latency histogram with fixed buckets, which can be updated concurrently.
+#[derive(Default)]
+pub struct Histogram {
+    /// Number of observations in each bucket, the last one being above all bounds.
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS.partition_point(|&bound| bound < seconds);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Get the number of observations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Get the sum of the observations.
+    pub fn sum(&self) -> Duration {
+        Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed))
+    }
+
+    fn render(&self, name: &str, labels: &str, out: &mut String) {
+        let mut cumulative = 0;
+        let bounds = BUCKETS.iter().map(|bound| bound.to_string());
+        for (bucket, bound) in self.buckets.iter().zip(bounds.chain(["+Inf".to_string()])) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}").unwrap();
+        }
+        let sum = self.sum().as_secs_f64();
+        writeln!(out, "{name}_sum{{{labels}}} {sum}").unwrap();
+        writeln!(out, "{name}_count{{{labels}}} {}", self.count()).unwrap();
+    }
+}
+
+/// The latency histograms of a storage. Pass them in
+/// [`LsmStorageOptions::metrics`](crate::lsm_storage::LsmStorageOptions::metrics) to have them
+/// recorded.
+#[derive(Default)]
+pub struct Metrics {
+    histograms: [Histogram; Operation::ALL.len()],
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the latency histogram of `operation`.
+    pub fn histogram(&self, operation: Operation) -> &Histogram {
+        &self.histograms[operation as usize]
+    }
+
+    /// Start timing `operation`. Its latency is recorded when the timer is dropped.
+    pub fn start_timer(&self, operation: Operation) -> OperationTimer<'_> {
+        OperationTimer {
+            histogram: self.histogram(operation),
+            start: Instant::now(),
+        }
+    }
+
+    /// Render the histograms in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let name = "mini_lsm_operation_duration_seconds";
+        let mut out = String::new();
+        writeln!(
+            out,
+            "# HELP {name} Latency of storage operations and background jobs."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE {name} histogram").unwrap();
+        for operation in Operation::ALL {
+            let labels = format!("operation=\"{}\"", operation.name());
+            self.histogram(operation).render(name, &labels, &mut out);
+        }
+        out
+    }
+}
+
+/// Records the time since it was started into a histogram when dropped.
+pub struct OperationTimer<'a> {
+    histogram: &'a Histogram,
+    start: Instant,
+}
+
+impl Drop for OperationTimer<'_> {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed());
+    }
+}
+
+/// Render the statistics of a storage in the Prometheus text exposition format.
+pub fn render_stats(stats: &StorageStats) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        writeln!(out, "# HELP mini_lsm_{name} {help}").unwrap();
+        writeln!(out, "# TYPE mini_lsm_{name} {kind}").unwrap();
+        for (labels, value) in samples {
+            writeln!(out, "mini_lsm_{name}{labels} {value}").unwrap();
+        }
+    };
+    let single = |value: &dyn ToString| vec![(String::new(), value.to_string())];
+    let per_level = |value: &dyn Fn(usize) -> String| -> Vec<_> {
+        (0..stats.levels.len())
+            .map(|level| (format!("{{level=\"{level}\"}}"), value(level)))
+            .collect()
+    };
+    metric(
+        "level_files",
+        "gauge",
+        "Number of SSTs in each level.",
+        &per_level(&|level| stats.levels[level].num_files.to_string()),
+    );
+    metric(
+        "level_bytes",
+        "gauge",
+        "Size of the SSTs in each level.",
+        &per_level(&|level| stats.levels[level].size_bytes.to_string()),
+    );
+    metric(
+        "blob_files",
+        "gauge",
+        "Number of blob files.",
+        &single(&stats.blob_files.num_files),
+    );
+    metric(
+        "blob_bytes",
+        "gauge",
+        "Size of the blob files.",
+        &single(&stats.blob_files.size_bytes),
+    );
+    metric(
+        "memtable_bytes",
+        "gauge",
+        "Bytes put into the current memtables.",
+        &single(&stats.memtable_bytes),
+    );
+    metric(
+        "imm_memtables",
+        "gauge",
+        "Number of immutable memtables waiting to be flushed.",
+        &single(&stats.num_imm_memtables),
+    );
+    metric(
+        "imm_memtable_bytes",
+        "gauge",
+        "Bytes put into the immutable memtables.",
+        &single(&stats.imm_memtable_bytes),
+    );
+    metric(
+        "block_cache_hits_total",
+        "counter",
+        "Block reads served from the block cache.",
+        &single(&stats.block_cache_hits),
+    );
+    metric(
+        "block_cache_misses_total",
+        "counter",
+        "Block reads that missed the block cache.",
+        &single(&stats.block_cache_misses),
+    );
+    metric(
+        "user_written_bytes_total",
+        "counter",
+        "Bytes of keys and values written by the application.",
+        &single(&stats.user_bytes_written),
+    );
+    metric(
+        "flush_written_bytes_total",
+        "counter",
+        "Bytes written by flushes.",
+        &single(&stats.flush_bytes_written),
+    );
+    metric(
+        "compaction_read_bytes_total",
+        "counter",
+        "Bytes read by compactions and blob garbage collection.",
+        &single(&stats.compaction_bytes_read),
+    );
+    metric(
+        "compaction_written_bytes_total",
+        "counter",
+        "Bytes written by compactions and blob garbage collection.",
+        &single(&stats.compaction_bytes_written),
+    );
+    metric(
+        "write_amplification",
+        "gauge",
+        "Bytes written to storage per byte written by the application.",
+        &single(&stats.write_amplification),
+    );
+    metric(
+        "read_amplification",
+        "gauge",
+        "The most tables and memtables a point lookup may check.",
+        &single(&stats.read_amplification),
+    );
+    metric(
+        "write_stall_slowdowns_total",
+        "counter",
+        "Writes delayed by a slowdown trigger.",
+        &single(&stats.write_stall.slowdowns),
+    );
+    metric(
+        "write_stall_stops_total",
+        "counter",
+        "Writes blocked by a stop trigger.",
+        &single(&stats.write_stall.stops),
+    );
+    metric(
+        "write_stall_seconds_total",
+        "counter",
+        "Time writes spent delayed or blocked.",
+        &single(&stats.write_stall.stall_duration.as_secs_f64()),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use super::*;
+
+#[test]
+fn test_histogram() {
+    let histogram = Histogram::default();
+    histogram.observe(Duration::from_micros(3));
+    histogram.observe(Duration::from_millis(1));
+    histogram.observe(Duration::from_secs(100));
+    assert_eq!(histogram.count(), 3);
+    assert_eq!(histogram.sum(), Duration::from_micros(100_001_003));
+
+    let mut out = String::new();
+    histogram.render("latency", "op=\"test\"", &mut out);
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), BUCKETS.len() + 3);
+    assert_eq!(lines[0], "latency_bucket{op=\"test\",le=\"0.00001\"} 1");
+    // A duration equal to a bound falls in its bucket.
+    assert_eq!(lines[4], "latency_bucket{op=\"test\",le=\"0.001\"} 2");
+    assert_eq!(lines[13], "latency_bucket{op=\"test\",le=\"60\"} 2");
+    assert_eq!(lines[14], "latency_bucket{op=\"test\",le=\"+Inf\"} 3");
+    assert_eq!(lines[15], "latency_sum{op=\"test\"} 100.001003");
+    assert_eq!(lines[16], "latency_count{op=\"test\"} 3");
+}
+
+#[test]
+fn test_timer() {
+    let metrics = Metrics::new();
+    {
+        let _timer = metrics.start_timer(Operation::Sync);
+        std::thread::sleep(Duration::from_millis(2));
+    }
+    let histogram = metrics.histogram(Operation::Sync);
+    assert_eq!(histogram.count(), 1);
+    assert!(histogram.sum() >= Duration::from_millis(2));
+    assert_eq!(metrics.histogram(Operation::Get).count(), 0);
+
+    let out = metrics.render();
+    assert!(out.starts_with("# HELP mini_lsm_operation_duration_seconds "));
+    assert!(out.contains("mini_lsm_operation_duration_seconds_count{operation=\"sync\"} 1\n"));
+    assert!(out.contains("mini_lsm_operation_duration_seconds_count{operation=\"get\"} 0\n"));
+}
@@ -0,0 +1,112 @@
+//! Throttles the writes of flushes and compactions, so that they leave disk bandwidth to
+//! foreground reads.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::clock::Clock;
+
+/// The longest a waiting write sleeps before checking the rate and the waiting writes again.
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+/// Which background job a write belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Flushes, which writers may be stalled on.
+    High,
+    /// Compactions and blob garbage collection.
+    Low,
+}
+
+struct State {
+    /// 0 means unlimited.
+    bytes_per_second: u64,
+    /// Tokens available, in bytes. Negative once a write larger than the tokens left went
+    /// through, in which case later writes wait for the debt to be paid back.
+    available: f64,
+    /// Time of the last refill, in milliseconds on the clock of the limiter.
+    last_refill: u64,
+    /// Number of high-priority writes waiting for tokens. Low-priority writes wait while any is.
+    high_priority_waiting: usize,
+}
+
+impl State {
+    fn refill(&mut self, now: u64) {
+        let elapsed_millis = now.saturating_sub(self.last_refill);
+        self.last_refill = now.max(self.last_refill);
+        // Allow bursts of up to one second worth of writes.
+        let burst = self.bytes_per_second as f64;
+        self.available = (self.available + elapsed_millis as f64 * burst / 1000.0).min(burst);
+    }
+}
+
+/// A token bucket limiting the bytes written per second on a clock. The rate can be changed
+/// while writes are waiting.
+pub struct RateLimiter {
+    state: Mutex<State>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter allowing `bytes_per_second` on `clock`, or any rate if it is 0.
+    pub fn new(bytes_per_second: u64, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            state: Mutex::new(State {
+                bytes_per_second,
+                available: bytes_per_second as f64,
+                last_refill: clock.now_millis(),
+                high_priority_waiting: 0,
+            }),
+            clock,
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.state.lock().bytes_per_second
+    }
+
+    /// Change the rate, or remove the limit if it is 0.
+    pub fn set_bytes_per_second(&self, bytes_per_second: u64) {
+        let mut state = self.state.lock();
+        state.refill(self.clock.now_millis());
+        state.bytes_per_second = bytes_per_second;
+        state.available = state.available.min(bytes_per_second as f64);
+    }
+
+    /// Wait until `bytes` may be written at the given priority. The wait sleeps on the clock in
+    /// steps of at most `MAX_WAIT`, so that changes of the rate and the high-priority writes that
+    /// went through are noticed.
+    pub fn request(&self, bytes: usize, priority: IoPriority) {
+        let mut state = self.state.lock();
+        if priority == IoPriority::High {
+            state.high_priority_waiting += 1;
+        }
+        loop {
+            if state.bytes_per_second == 0 {
+                break;
+            }
+            state.refill(self.clock.now_millis());
+            let is_turn = priority == IoPriority::High || state.high_priority_waiting == 0;
+            if is_turn && state.available >= 0.0 {
+                state.available -= bytes as f64;
+                break;
+            }
+            // Wait for the debt to be paid back, or for the high-priority writes to go through.
+            let wait_millis = if state.available < 0.0 {
+                (-state.available * 1000.0 / state.bytes_per_second as f64).ceil() as u64
+            } else {
+                0
+            };
+            let wait = Duration::from_millis(wait_millis.max(1)).min(MAX_WAIT);
+            MutexGuard::unlocked(&mut state, || self.clock.sleep(wait));
+        }
+        if priority == IoPriority::High {
+            state.high_priority_waiting -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::*;
+use crate::clock::ManualClock;
+
+/// A clock whose sleeps only end once the test moves it forward.
+#[derive(Default)]
+struct SteppedClock {
+    /// The current time, and the number of threads sleeping.
+    state: Mutex<(u64, usize)>,
+    advanced: Condvar,
+}
+
+impl SteppedClock {
+    fn advance(&self, duration: Duration) {
+        self.state.lock().0 += duration.as_millis() as u64;
+        self.advanced.notify_all();
+    }
+
+    /// Wait for `sleepers` threads to sleep on the clock.
+    fn wait_for_sleepers(&self, sleepers: usize) {
+        while self.state.lock().1 != sleepers {
+            std::thread::yield_now();
+        }
+    }
+}
+
+impl Clock for SteppedClock {
+    fn now_millis(&self) -> u64 {
+        self.state.lock().0
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut state = self.state.lock();
+        let wake_at = state.0 + duration.as_millis() as u64;
+        state.1 += 1;
+        while state.0 < wake_at {
+            self.advanced.wait(&mut state);
+        }
+        state.1 -= 1;
+    }
+}
+
+#[test]
+fn test_unlimited() {
+    let clock = Arc::new(ManualClock::new(0));
+    let limiter = RateLimiter::new(0, clock.clone());
+    for _ in 0..1000 {
+        limiter.request(1 << 20, IoPriority::Low);
+    }
+    assert_eq!(clock.now_millis(), 0);
+}
+
+#[test]
+fn test_rate_limit() {
+    let clock = Arc::new(ManualClock::new(0));
+    let limiter = RateLimiter::new(100_000, clock.clone());
+    // The first second worth of bytes is a burst, and the 11th write goes through on it too,
+    // leaving a debt that each later write pays back in 100ms.
+    let mut schedule = Vec::new();
+    for _ in 0..15 {
+        limiter.request(10_000, IoPriority::Low);
+        schedule.push(clock.now_millis());
+    }
+    let mut expected = vec![0; 11];
+    expected.extend([100, 200, 300, 400]);
+    assert_eq!(schedule, expected);
+}
+
+#[test]
+fn test_change_rate_at_runtime() {
+    let clock = Arc::new(SteppedClock::default());
+    let limiter = Arc::new(RateLimiter::new(1000, clock.clone()));
+    limiter.request(10_000, IoPriority::High);
+    let waiter = {
+        let (limiter, clock) = (limiter.clone(), clock.clone());
+        std::thread::spawn(move || {
+            limiter.request(1, IoPriority::High);
+            clock.now_millis()
+        })
+    };
+    // Paying the debt back would take 9 seconds at the old rate, but the waiter notices the new
+    // one after its current step.
+    clock.wait_for_sleepers(1);
+    limiter.set_bytes_per_second(0);
+    clock.advance(MAX_WAIT);
+    assert_eq!(waiter.join().unwrap(), 10);
+    assert_eq!(limiter.bytes_per_second(), 0);
+}
+
+#[test]
+fn test_high_priority_first() {
+    let clock = Arc::new(SteppedClock::default());
+    let limiter = Arc::new(RateLimiter::new(10_000, clock.clone()));
+    // Put the bucket 1000 bytes in debt, so that the next writes have to wait 100ms.
+    limiter.request(11_000, IoPriority::Low);
+    let request = |bytes, priority| {
+        let (limiter, clock) = (limiter.clone(), clock.clone());
+        std::thread::spawn(move || {
+            limiter.request(bytes, priority);
+            clock.now_millis()
+        })
+    };
+    let high = request(500, IoPriority::High);
+    clock.wait_for_sleepers(1);
+    let low = request(1, IoPriority::Low);
+    clock.wait_for_sleepers(2);
+
+    // The high-priority write goes first once the debt is paid back, and puts the bucket in debt
+    // again for 50ms, which the low-priority one waits for.
+    clock.advance(Duration::from_millis(100));
+    assert_eq!(high.join().unwrap(), 100);
+    clock.wait_for_sleepers(1);
+    clock.advance(Duration::from_millis(50));
+    assert_eq!(low.join().unwrap(), 150);
+}
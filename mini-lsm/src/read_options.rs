@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Options of a single `get`, `multi_get` or `scan`.
+#[derive(Clone)]
+pub struct ReadOptions {
+    /// Add the blocks read from storage to the block cache. Turn it off for large scans that
+    /// would evict the blocks other reads need. Blocks read without `verify_checksums` are never
+    /// added.
+    pub fill_cache: bool,
+    /// Check the checksum of every block read from storage, and fail the read on a mismatch.
+    /// Blocks found in the block cache were checked when they were read.
+    pub verify_checksums: bool,
+    /// When an iterator reads a block from storage, also read the blocks after it, up to this
+    /// many bytes in total, in the same request. 0 lets iterators read ahead on their own once
+    /// they read blocks in order, up to `max_auto_readahead_size`.
+    pub readahead_size: usize,
+    /// Largest readahead iterators grow to when `readahead_size` is 0. 0 reads one block at a
+    /// time.
+    pub max_auto_readahead_size: usize,
+    /// Read the next readahead window on a background thread while the current one is consumed.
+    /// Each iterator has one such thread, which is stopped when the iterator is dropped.
+    pub async_prefetch: bool,
+    /// Number of threads `multi_get` splits its keys across. 0 or 1 looks them all up on the
+    /// calling thread.
+    pub multi_get_threads: usize,
+    /// Counts the work done by the read, including the iteration of a scan.
+    pub perf_context: Option<Arc<PerfContext>>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            fill_cache: true,
+            verify_checksums: true,
+            readahead_size: 0,
+            max_auto_readahead_size: 256 * 1024,
+            async_prefetch: false,
+            multi_get_threads: 1,
+            perf_context: None,
+        }
+    }
+}
+
+impl ReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the work done by the read into `perf_context`.
+    pub fn with_perf_context(mut self, perf_context: Arc<PerfContext>) -> Self {
+        self.perf_context = Some(perf_context);
+        self
+    }
+
+    /// Add `count` to a counter of the perf context, if any.
+    pub(crate) fn count(&self, counter: impl Fn(&PerfContext) -> &AtomicU64, count: u64) {
+        if let Some(perf_context) = &self.perf_context {
+            counter(perf_context).fetch_add(count, Ordering::Relaxed);
+        }
+    }
+}
+
+/// What reads did, counted as they do it. A perf context can be shared by several reads to add
+/// up their work.
+#[derive(Debug, Default)]
+pub struct PerfContext {
+    pub(crate) memtables_probed: AtomicU64,
+    pub(crate) sstables_checked: AtomicU64,
+    pub(crate) sstables_skipped: AtomicU64,
+    pub(crate) block_cache_hits: AtomicU64,
+    pub(crate) blocks_read: AtomicU64,
+    pub(crate) block_bytes_read: AtomicU64,
+    pub(crate) bytes_decoded: AtomicU64,
+}
+
+/// The values of the counters of a [`PerfContext`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfCounters {
+    /// Memtables searched, mutable and immutable.
+    pub memtables_probed: u64,
+    /// SSTs searched.
+    pub sstables_checked: u64,
+    /// SSTs not searched, because their key range does not overlap the keys read.
+    pub sstables_skipped: u64,
+    /// Blocks found in the block cache.
+    pub block_cache_hits: u64,
+    /// Blocks read from storage.
+    pub blocks_read: u64,
+    /// Bytes of the blocks read from storage, as stored.
+    pub block_bytes_read: u64,
+    /// Bytes of the blocks read from storage, once decompressed.
+    pub bytes_decoded: u64,
+}
+
+impl PerfContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the current values of the counters.
+    pub fn counters(&self) -> PerfCounters {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        PerfCounters {
+            memtables_probed: load(&self.memtables_probed),
+            sstables_checked: load(&self.sstables_checked),
+            sstables_skipped: load(&self.sstables_skipped),
+            block_cache_hits: load(&self.block_cache_hits),
+            blocks_read: load(&self.blocks_read),
+            block_bytes_read: load(&self.block_bytes_read),
+            bytes_decoded: load(&self.bytes_decoded),
+        }
+    }
+
+    /// Set all counters back to zero.
+    pub fn reset(&self) {
+        for counter in [
+            &self.memtables_probed,
+            &self.sstables_checked,
+            &self.sstables_skipped,
+            &self.block_cache_hits,
+            &self.blocks_read,
+            &self.block_bytes_read,
+            &self.bytes_decoded,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
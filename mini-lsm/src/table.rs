@@ -1,17 +1,25 @@
 mod builder;
+mod compression;
 mod iterator;
+mod readahead;
 
-use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 pub use builder::SsTableBuilder;
 use bytes::{Buf, BufMut, Bytes};
+pub use compression::CompressionType;
+use compression::{decompress_block, ZstdDictionary};
 pub use iterator::SsTableIterator;
+use readahead::Readahead;
 
-use crate::block::Block;
+use crate::backend::{LocalBackend, RandomAccessFile, StorageBackend, WritableFile};
+use crate::block::{Block, BlockIterator};
 use crate::lsm_storage::BlockCache;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::read_options::ReadOptions;
+use crate::trace::{enter_span, record_field};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlockMeta {
@@ -61,8 +69,8 @@ impl BlockMeta {
 /// pub struct FileObject(Bytes);
 ///
 /// impl FileObject {
-///     pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
-///         Ok(self.0[offset as usize..(offset + len) as usize].to_vec())
+///     pub fn read(&self, offset: u64, len: u64) -> Result<Bytes> {
+///         Ok(self.0.slice(offset as usize..(offset + len) as usize))
 ///    }
 ///     pub fn size(&self) -> u64 {
 ///         self.0.len() as u64
@@ -77,33 +85,111 @@ impl BlockMeta {
 ///     }
 /// }
 /// ```
-pub struct FileObject(File, u64);
+pub struct FileObject(Arc<dyn RandomAccessFile>);
 
 impl FileObject {
-    pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
-        use std::os::unix::fs::FileExt;
-        let mut data = vec![0; len as usize];
-        self.0.read_exact_at(&mut data[..], offset)?;
-        Ok(data)
+    /// Read `len` bytes at `offset`. The returned buffer is owned by the caller, so blocks and
+    /// metadata decoded from it can keep slices of it without copying.
+    pub fn read(&self, offset: u64, len: u64) -> Result<Bytes> {
+        self.0.read_at(offset, len)
     }
 
     pub fn size(&self) -> u64 {
-        self.1
+        self.0.size()
     }
 
     /// Create a new file object (day 2) and write the file to the disk (day 4).
     pub fn create(path: &Path, data: Vec<u8>) -> Result<Self> {
-        std::fs::write(path, &data)?;
-        Ok(FileObject(
-            File::options().read(true).write(false).open(path)?,
-            data.len() as u64,
-        ))
+        Self::create_with_backend(Arc::new(LocalBackend::default()), path, data)
+    }
+
+    /// Durably write the file through the given backend and open it for reading.
+    pub fn create_with_backend(
+        backend: Arc<dyn StorageBackend>,
+        path: &Path,
+        data: Vec<u8>,
+    ) -> Result<Self> {
+        let mut writer = FileWriter::create(backend, path)?;
+        writer.append(&data)?;
+        writer.finish()
     }
 
     pub fn open(path: &Path) -> Result<Self> {
-        let f = File::options().read(true).write(false).open(path)?;
-        let len = f.metadata()?.len();
-        Ok(FileObject(f, len))
+        Self::open_with_backend(&LocalBackend::default(), path)
+    }
+
+    /// Open an existing file for reading through the given backend.
+    pub fn open_with_backend(backend: &dyn StorageBackend, path: &Path) -> Result<Self> {
+        Ok(FileObject(backend.open(path)?))
+    }
+}
+
+/// Durably writes a file that is produced incrementally.
+///
+/// The data is appended to a temporary file, which is synced and then atomically renamed to the
+/// final path by [`finish`](Self::finish), and the directory is synced last. After a crash, the
+/// final path is therefore either missing or complete, and once `finish` returns it survives a
+/// crash. If the writer is dropped without finishing, the temporary file is removed.
+pub(crate) struct FileWriter {
+    backend: Arc<dyn StorageBackend>,
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+    file: Option<Box<dyn WritableFile>>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl FileWriter {
+    pub fn create(backend: Arc<dyn StorageBackend>, path: &Path) -> Result<Self> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let file = backend.create(&tmp_path)?;
+        Ok(Self {
+            backend,
+            rate_limiter: None,
+            file: Some(file),
+            tmp_path,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Throttle the appends with the given rate limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>, priority: IoPriority) {
+        self.rate_limiter = Some((rate_limiter, priority));
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> Result<()> {
+        if let Some((rate_limiter, priority)) = &self.rate_limiter {
+            rate_limiter.request(data.len(), *priority);
+        }
+        self.file.as_mut().unwrap().append(data)
+    }
+
+    /// Make the file durable under its final path and open it for reading.
+    pub fn finish(mut self) -> Result<FileObject> {
+        let mut file = self.file.take().unwrap();
+        let result = file.sync();
+        drop(file);
+        if let Err(e) = result.and_then(|_| self.backend.rename(&self.tmp_path, &self.path)) {
+            // Best effort: the temporary file is garbage either way.
+            let _ = self.backend.delete(&self.tmp_path);
+            return Err(e);
+        }
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        self.backend.sync_dir(dir)?;
+        FileObject::open_with_backend(self.backend.as_ref(), &self.path)
+    }
+}
+
+impl Drop for FileWriter {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            // Best effort: the temporary file is garbage either way.
+            let _ = self.backend.delete(&self.tmp_path);
+        }
     }
 }
 
@@ -111,6 +197,8 @@ pub struct SsTable {
     file: FileObject,
     block_metas: Vec<BlockMeta>,
     block_meta_offset: usize,
+    /// The zstd dictionary shared by the blocks of this table, if any.
+    dictionary: Option<ZstdDictionary>,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
 }
@@ -124,13 +212,31 @@ impl SsTable {
     /// Open SSTable from a file.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
         let len = file.size();
+        if len < 4 {
+            bail!("SST file too short: {} bytes", len);
+        }
         let raw_meta_offset = file.read(len - 4, 4)?;
         let block_meta_offset = (&raw_meta_offset[..]).get_u32() as u64;
-        let raw_meta = file.read(block_meta_offset, len - 4 - block_meta_offset)?;
+        if block_meta_offset > len - 4 {
+            bail!("invalid block meta offset: {}", block_meta_offset);
+        }
+        // The meta section is the block metas, followed by the dictionary and its length.
+        let mut raw_meta = file.read(block_meta_offset, len - 4 - block_meta_offset)?;
+        if raw_meta.len() < 4 {
+            bail!("SST meta section too short: {} bytes", raw_meta.len());
+        }
+        let raw_dictionary_len = raw_meta.split_off(raw_meta.len() - 4);
+        let dictionary_len = (&raw_dictionary_len[..]).get_u32() as usize;
+        if dictionary_len > raw_meta.len() {
+            bail!("invalid dictionary length: {}", dictionary_len);
+        }
+        let dictionary = raw_meta.split_off(raw_meta.len() - dictionary_len);
+        let dictionary = (!dictionary.is_empty()).then(|| ZstdDictionary::new(dictionary));
         Ok(Self {
             file,
-            block_metas: BlockMeta::decode_block_meta(&raw_meta[..]),
+            block_metas: BlockMeta::decode_block_meta(raw_meta),
             block_meta_offset: block_meta_offset as usize,
+            dictionary,
             id,
             block_cache,
         })
@@ -138,27 +244,120 @@ impl SsTable {
 
     /// Read a block from the disk.
     pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
+        self.read_block_with_options(block_idx, &ReadOptions::default())
+    }
+
+    /// Get the offsets of the start and the end of a block in the file.
+    fn block_range(&self, block_idx: usize) -> (usize, usize) {
         let offset = self.block_metas[block_idx].offset;
         let offset_end = self
             .block_metas
             .get(block_idx + 1)
             .map_or(self.block_meta_offset, |x| x.offset);
+        (offset, offset_end)
+    }
+
+    /// Read a block from the disk, counting the read into the perf context of `options`.
+    fn read_block_with_options(
+        &self,
+        block_idx: usize,
+        options: &ReadOptions,
+    ) -> Result<Arc<Block>> {
+        let (offset, offset_end) = self.block_range(block_idx);
         let block_data = self
             .file
             .read(offset as u64, (offset_end - offset) as u64)?;
-        Ok(Arc::new(Block::decode(&block_data[..])))
+        options.count(|perf| &perf.block_bytes_read, block_data.len() as u64);
+        self.decode_block(block_idx, block_data, options)
+    }
+
+    /// Check and decompress a block as stored in the file.
+    fn decode_block(
+        &self,
+        block_idx: usize,
+        mut block_data: Bytes,
+        options: &ReadOptions,
+    ) -> Result<Arc<Block>> {
+        enter_span!(
+            span = "read_block",
+            sst_id = self.id,
+            block_idx,
+            bytes = tracing::field::Empty
+        );
+        // Every block ends with the codec it is compressed with, and a checksum of both.
+        if block_data.len() < 5 {
+            bail!("block {} of SST {} too short", block_idx, self.id);
+        }
+        record_field!(span, bytes, block_data.len());
+        options.count(|perf| &perf.blocks_read, 1);
+        let checksum = (&block_data.split_off(block_data.len() - 4)[..]).get_u32();
+        if options.verify_checksums && crc32fast::hash(&block_data) != checksum {
+            bail!(
+                "checksum mismatch in block {} of SST {}",
+                block_idx,
+                self.id
+            );
+        }
+        let codec = block_data.split_off(block_data.len() - 1)[0];
+        let block_data = decompress_block(codec, block_data, self.dictionary.as_ref())?;
+        options.count(|perf| &perf.bytes_decoded, block_data.len() as u64);
+        Ok(Arc::new(Block::decode(block_data)))
     }
 
     /// Read a block from disk, with block cache.
     pub fn read_block_cached(&self, block_idx: usize) -> Result<Arc<Block>> {
-        if let Some(ref block_cache) = self.block_cache {
-            let blk = block_cache
-                .try_get_with((self.id, block_idx), || self.read_block(block_idx))
-                .map_err(|e| anyhow!("{}", e))?;
-            Ok(blk)
+        self.read_block_cached_with_options(block_idx, &ReadOptions::default())
+    }
+
+    /// Read a block from disk, with block cache, as a read with `options`.
+    pub fn read_block_cached_with_options(
+        &self,
+        block_idx: usize,
+        options: &ReadOptions,
+    ) -> Result<Arc<Block>> {
+        self.read_block_through_cache(block_idx, options, || {
+            self.read_block_with_options(block_idx, options)
+        })
+    }
+
+    /// Get a block from the block cache, or read it with `read`. The block read is only added to
+    /// the cache if `options.fill_cache` and `options.verify_checksums` are set, so that cached
+    /// blocks are always verified.
+    fn read_block_through_cache(
+        &self,
+        block_idx: usize,
+        options: &ReadOptions,
+        read: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        enter_span!(
+            span = "read_block_cached",
+            sst_id = self.id,
+            block_idx,
+            cache_hit = tracing::field::Empty
+        );
+        let Some(block_cache) = &self.block_cache else {
+            return read();
+        };
+        let mut cache_hit = true;
+        let block = if options.fill_cache && options.verify_checksums {
+            block_cache.get_or_read(self.id, block_idx, || {
+                cache_hit = false;
+                read()
+            })?
         } else {
-            self.read_block(block_idx)
+            match block_cache.get(self.id, block_idx) {
+                Some(block) => block,
+                None => {
+                    cache_hit = false;
+                    read()?
+                }
+            }
+        };
+        record_field!(span, cache_hit, cache_hit);
+        if cache_hit {
+            options.count(|perf| &perf.block_cache_hits, 1);
         }
+        Ok(block)
     }
 
     /// Find the block that may contain `key`.
@@ -168,10 +367,47 @@ impl SsTable {
             .saturating_sub(1)
     }
 
+    /// Get the first key of the table.
+    pub fn first_key(&self) -> &[u8] {
+        self.block_metas
+            .first()
+            .map_or(&[][..], |meta| &meta.first_key[..])
+    }
+
+    /// Get the offsets and first keys of the data blocks.
+    pub fn block_meta(&self) -> &[BlockMeta] {
+        &self.block_metas
+    }
+
     /// Get number of data blocks.
     pub fn num_of_blocks(&self) -> usize {
         self.block_metas.len()
     }
+
+    /// Get the ID of the table.
+    pub fn sst_id(&self) -> usize {
+        self.id
+    }
+
+    /// Get the size of the table file in bytes.
+    pub fn table_size(&self) -> u64 {
+        self.file.size()
+    }
+
+    /// Get the first and last keys of the table. The last key is read from the last block.
+    pub fn key_range(&self) -> Result<(Bytes, Bytes)> {
+        let Some(first) = self.block_metas.first() else {
+            bail!("SST {} has no blocks", self.id);
+        };
+        let block = self.read_block_cached(self.block_metas.len() - 1)?;
+        let mut iter = BlockIterator::create_and_seek_to_first(block);
+        let mut last_key = Bytes::new();
+        while iter.is_valid() {
+            last_key = iter.key_bytes();
+            iter.next();
+        }
+        Ok((first.first_key.clone(), last_key))
+    }
 }
 
 #[cfg(test)]
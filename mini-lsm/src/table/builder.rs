@@ -1,20 +1,46 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::Result;
-use bytes::BufMut;
+use bytes::{BufMut, Bytes};
 
-use super::{BlockMeta, FileObject, SsTable};
-use crate::block::BlockBuilder;
+use super::compression::{ZstdDictionary, ZSTD_DICTIONARY_CODEC};
+use super::{BlockMeta, CompressionType, FileWriter, SsTable};
+use crate::backend::{LocalBackend, StorageBackend};
+use crate::block::{BlockBuilder, EntryMeta};
 use crate::lsm_storage::BlockCache;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+
+/// Blocks are held back until they add up to this many times the dictionary size, to give the
+/// dictionary trainer enough samples.
+const DICTIONARY_SAMPLE_RATIO: usize = 100;
+
+/// Blocks held back as samples never add up to more than this, whatever the dictionary size.
+const MAX_DICTIONARY_SAMPLE_BYTES: usize = 4 << 20;
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
     builder: BlockBuilder,
     first_key: Vec<u8>,
+    /// Encoded data not written to the file yet. Without a file, this is the whole SSTable.
     data: Vec<u8>,
+    /// Length of the data already written to the file.
+    written: usize,
     pub(super) meta: Vec<BlockMeta>,
     block_size: usize,
+    compression: CompressionType,
+    /// Maximum size of the zstd dictionary to train, or 0 if no dictionary is trained.
+    dictionary_size: usize,
+    /// Encoded blocks and their first keys held back as samples until the dictionary is trained.
+    pub(super) samples: Vec<(Bytes, Bytes)>,
+    sampled_bytes: usize,
+    dictionary: Option<ZstdDictionary>,
+    backend: Arc<dyn StorageBackend>,
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+    /// The file finished blocks are streamed to, and its final path.
+    writer: Option<(FileWriter, PathBuf)>,
+    /// The first error hit while streaming, reported by `build`.
+    error: Option<anyhow::Error>,
 }
 
 impl SsTableBuilder {
@@ -22,43 +48,187 @@ impl SsTableBuilder {
     pub fn new(block_size: usize) -> Self {
         Self {
             data: Vec::new(),
+            written: 0,
             meta: Vec::new(),
             first_key: Vec::new(),
             block_size,
+            compression: CompressionType::default(),
+            dictionary_size: 0,
+            samples: Vec::new(),
+            sampled_bytes: 0,
+            dictionary: None,
             builder: BlockBuilder::new(block_size),
+            backend: Arc::new(LocalBackend::default()),
+            rate_limiter: None,
+            writer: None,
+            error: None,
         }
     }
 
+    /// Create a builder that streams every finished block to a file for `path`, so that memory
+    /// usage is bounded by one block plus the block metas. The SSTable must be built to the same
+    /// path.
+    pub fn create(
+        block_size: usize,
+        backend: Arc<dyn StorageBackend>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let writer = FileWriter::create(backend.clone(), path.as_ref())?;
+        let mut builder = Self::new(block_size);
+        builder.backend = backend;
+        builder.writer = Some((writer, path.as_ref().to_path_buf()));
+        Ok(builder)
+    }
+
+    /// Set the backend the SSTable file is written to.
+    pub fn set_backend(&mut self, backend: Arc<dyn StorageBackend>) {
+        assert!(
+            self.writer.is_none(),
+            "streaming builder already has a file"
+        );
+        self.backend = backend;
+    }
+
+    /// Throttle the writes of the SSTable file with the given rate limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>, priority: IoPriority) {
+        if let Some((writer, _)) = self.writer.as_mut() {
+            writer.set_rate_limiter(rate_limiter.clone(), priority);
+        }
+        self.rate_limiter = Some((rate_limiter, priority));
+    }
+
+    /// Set the codec data blocks are compressed with.
+    pub fn set_compression(&mut self, compression: CompressionType) {
+        self.compression = compression;
+    }
+
+    /// Train a zstd dictionary of at most `max_size` bytes from the first blocks of the table,
+    /// store it in the table, and compress every block with it. This takes precedence over
+    /// [`set_compression`](Self::set_compression). Blocks are buffered in memory until there are
+    /// enough samples to train the dictionary, up to 4 MiB.
+    pub fn set_zstd_dictionary(&mut self, max_size: usize) {
+        assert!(
+            self.meta.is_empty(),
+            "blocks already written without dictionary"
+        );
+        self.dictionary_size = max_size;
+    }
+
     /// Adds a key-value pair to SSTable
     pub fn add(&mut self, key: &[u8], value: &[u8]) {
+        self.add_entry(key, value, EntryMeta::default());
+    }
+
+    /// Adds a key-value pair with the given attributes to SSTable
+    pub fn add_entry(&mut self, key: &[u8], value: &[u8], meta: EntryMeta) {
         if self.first_key.is_empty() {
             self.first_key = key.to_vec();
         }
 
-        if self.builder.add(key, value) {
+        if self.builder.add_entry(key, value, meta) {
             return;
         }
         // create a new block builder and append block data
         self.finish_block();
 
         // add the key-value pair to the next block
-        assert!(self.builder.add(key, value));
+        assert!(self.builder.add_entry(key, value, meta));
         self.first_key = key.to_vec();
     }
 
-    /// Get the estimated size of the SSTable.
+    /// Check if no key-value pair has been added.
+    pub fn is_empty(&self) -> bool {
+        self.meta.is_empty() && self.samples.is_empty() && self.builder.is_empty()
+    }
+
+    /// Get the estimated size of the SSTable, counting the blocks held back as dictionary samples
+    /// at their uncompressed size.
     pub fn estimated_size(&self) -> usize {
-        self.data.len()
+        self.data_offset() + self.sampled_bytes
+    }
+
+    /// Get the offset in the file of the next data written.
+    fn data_offset(&self) -> usize {
+        self.written + self.data.len()
     }
 
     fn finish_block(&mut self) {
         let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
         let encoded_block = builder.build().encode();
+        let first_key = std::mem::take(&mut self.first_key).into();
+        if self.dictionary_size > 0 {
+            self.sampled_bytes += encoded_block.len();
+            self.samples.push((encoded_block, first_key));
+            let sample_size = self.dictionary_size * DICTIONARY_SAMPLE_RATIO;
+            if self.sampled_bytes >= sample_size.min(MAX_DICTIONARY_SAMPLE_BYTES) {
+                self.train_dictionary();
+            }
+            return;
+        }
+        self.write_block(&encoded_block, first_key);
+    }
+
+    /// Train the dictionary from the sampled blocks, and write them out.
+    fn train_dictionary(&mut self) {
+        let samples = std::mem::take(&mut self.samples);
+        self.sampled_bytes = 0;
+        // Fall back to the regular compression if there are too few samples to train on.
+        self.dictionary = ZstdDictionary::train(
+            &samples.iter().map(|(block, _)| block).collect::<Vec<_>>(),
+            self.dictionary_size,
+        )
+        .ok();
+        self.dictionary_size = 0;
+        for (encoded_block, first_key) in samples {
+            self.write_block(&encoded_block, first_key);
+        }
+    }
+
+    fn write_block(&mut self, encoded_block: &[u8], first_key: Bytes) {
         self.meta.push(BlockMeta {
-            offset: self.data.len(),
-            first_key: std::mem::take(&mut self.first_key).into(),
+            offset: self.data_offset(),
+            first_key,
         });
-        self.data.extend(encoded_block);
+        let block_start = self.data.len();
+        // Store the block uncompressed if compressing fails or does not make it smaller.
+        let compressed = match &self.dictionary {
+            Some(dictionary) => Some((dictionary.compress(encoded_block), ZSTD_DICTIONARY_CODEC)),
+            None if self.compression != CompressionType::None => Some((
+                self.compression.compress(encoded_block),
+                self.compression.to_u8(),
+            )),
+            None => None,
+        };
+        match compressed {
+            Some((Ok(data), codec)) if data.len() < encoded_block.len() => {
+                self.data.extend(data);
+                self.data.put_u8(codec);
+            }
+            _ => {
+                self.data.extend(encoded_block);
+                self.data.put_u8(CompressionType::None.to_u8());
+            }
+        }
+        // The checksum covers the codec as well as the block.
+        let checksum = crc32fast::hash(&self.data[block_start..]);
+        self.data.put_u32(checksum);
+        self.write_data();
+    }
+
+    /// Write out the buffered data if the builder streams to a file.
+    fn write_data(&mut self) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Some((writer, _)) = self.writer.as_mut() {
+            match writer.append(&self.data) {
+                Ok(()) => {
+                    self.written += self.data.len();
+                    self.data.clear();
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
     }
 
     /// Builds the SSTable and writes it to the given path. No need to actually write to disk until
@@ -70,16 +240,45 @@ impl SsTableBuilder {
         path: impl AsRef<Path>,
     ) -> Result<SsTable> {
         self.finish_block();
-        let mut buf = self.data;
-        let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, &mut buf);
-        buf.put_u32(meta_offset as u32);
-        let file = FileObject::create(path.as_ref(), buf)?;
+        if !self.samples.is_empty() {
+            self.train_dictionary();
+        }
+        let meta_offset = self.data_offset();
+        BlockMeta::encode_block_meta(&self.meta, &mut self.data);
+        let dictionary = self.dictionary.as_ref().map(|d| d.raw().clone());
+        let dictionary_len = dictionary.as_ref().map_or(0, |d| d.len());
+        self.data
+            .put_slice(dictionary.as_deref().unwrap_or_default());
+        self.data.put_u32(dictionary_len as u32);
+        self.data.put_u32(meta_offset as u32);
+        let file = match self.writer.take() {
+            Some((mut writer, writer_path)) => {
+                assert_eq!(
+                    writer_path,
+                    path.as_ref(),
+                    "streaming builder built to a different path"
+                );
+                if let Some(e) = self.error.take() {
+                    return Err(e);
+                }
+                writer.append(&self.data)?;
+                writer.finish()?
+            }
+            None => {
+                let mut writer = FileWriter::create(self.backend, path.as_ref())?;
+                if let Some((rate_limiter, priority)) = self.rate_limiter {
+                    writer.set_rate_limiter(rate_limiter, priority);
+                }
+                writer.append(&self.data)?;
+                writer.finish()?
+            }
+        };
         Ok(SsTable {
             id,
             file,
             block_metas: self.meta,
             block_meta_offset: meta_offset,
+            dictionary: self.dictionary,
             block_cache,
         })
     }
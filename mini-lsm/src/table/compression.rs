@@ -0,0 +1,120 @@
+use std::io::Read;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+/// The codec a data block is compressed with. It is stored as the last byte of every block on
+/// disk, so tables written with different codecs can be read side by side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionType {
+    #[default]
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec byte of blocks compressed with zstd and the dictionary of their table.
+pub(super) const ZSTD_DICTIONARY_CODEC: u8 = 4;
+
+impl CompressionType {
+    pub(super) fn to_u8(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Snappy => 1,
+            CompressionType::Lz4 => 2,
+            CompressionType::Zstd => 3,
+        }
+    }
+
+    pub(super) fn from_u8(codec: u8) -> Result<Self> {
+        Ok(match codec {
+            0 => CompressionType::None,
+            1 => CompressionType::Snappy,
+            2 => CompressionType::Lz4,
+            3 => CompressionType::Zstd,
+            _ => bail!("unknown compression codec: {}", codec),
+        })
+    }
+
+    /// Compress an encoded block.
+    pub(super) fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Snappy => snap::raw::Encoder::new().compress_vec(data)?,
+            CompressionType::Lz4 => lz4_flex::compress_prepend_size(data),
+            CompressionType::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL)?,
+        })
+    }
+
+    /// Decompress an encoded block. Uncompressed blocks are returned as is, without copying.
+    pub(super) fn decompress(self, data: Bytes) -> Result<Bytes> {
+        Ok(match self {
+            CompressionType::None => data,
+            CompressionType::Snappy => snap::raw::Decoder::new().decompress_vec(&data)?.into(),
+            CompressionType::Lz4 => lz4_flex::decompress_size_prepended(&data)?.into(),
+            CompressionType::Zstd => zstd::stream::decode_all(&data[..])?.into(),
+        })
+    }
+}
+
+/// A zstd dictionary shared by all blocks of a table, trained from samples of its data blocks.
+pub(super) struct ZstdDictionary {
+    raw: Bytes,
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+}
+
+impl ZstdDictionary {
+    /// Train a dictionary of at most `max_size` bytes from sample blocks.
+    pub fn train<S: AsRef<[u8]>>(samples: &[S], max_size: usize) -> Result<Self> {
+        Ok(Self::new(
+            zstd::dict::from_samples(samples, max_size)?.into(),
+        ))
+    }
+
+    pub fn new(raw: Bytes) -> Self {
+        Self {
+            encoder: EncoderDictionary::copy(&raw, ZSTD_LEVEL),
+            decoder: DecoderDictionary::copy(&raw),
+            raw,
+        }
+    }
+
+    /// The dictionary as it is stored in the SST.
+    pub fn raw(&self) -> &Bytes {
+        &self.raw
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut compressor = zstd::bulk::Compressor::with_prepared_dictionary(&self.encoder)?;
+        Ok(compressor.compress(data)?)
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Bytes> {
+        let mut decoder =
+            zstd::stream::read::Decoder::with_prepared_dictionary(data, &self.decoder)?;
+        let mut buf = Vec::new();
+        decoder.read_to_end(&mut buf)?;
+        Ok(buf.into())
+    }
+}
+
+/// Decompress a block stored with the given codec byte.
+pub(super) fn decompress_block(
+    codec: u8,
+    data: Bytes,
+    dictionary: Option<&ZstdDictionary>,
+) -> Result<Bytes> {
+    if codec == ZSTD_DICTIONARY_CODEC {
+        match dictionary {
+            Some(dictionary) => dictionary.decompress(&data),
+            None => bail!("block compressed with a dictionary, but the table has none"),
+        }
+    } else {
+        CompressionType::from_u8(codec)?.decompress(data)
+    }
+}
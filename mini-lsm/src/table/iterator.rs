@@ -2,53 +2,82 @@ use std::sync::Arc;
 
 use anyhow::Result;
 
-use super::SsTable;
-use crate::block::BlockIterator;
+use super::{Readahead, SsTable};
+use crate::block::{BlockIterator, EntryMeta};
 use crate::iterators::StorageIterator;
+use crate::read_options::ReadOptions;
 
 /// An iterator over the contents of an SSTable.
 pub struct SsTableIterator {
     table: Arc<SsTable>,
     blk_iter: BlockIterator,
     blk_idx: usize,
+    options: ReadOptions,
+    readahead: Readahead,
 }
 
 impl SsTableIterator {
-    fn seek_to_first_inner(table: &Arc<SsTable>) -> Result<(usize, BlockIterator)> {
+    fn seek_to_first_inner(
+        table: &Arc<SsTable>,
+        options: &ReadOptions,
+        readahead: &mut Readahead,
+    ) -> Result<(usize, BlockIterator)> {
         Ok((
             0,
-            BlockIterator::create_and_seek_to_first(table.read_block_cached(0)?),
+            BlockIterator::create_and_seek_to_first(
+                table.read_block_for_iter(0, options, readahead)?,
+            ),
         ))
     }
 
     /// Create a new iterator and seek to the first key-value pair.
     pub fn create_and_seek_to_first(table: Arc<SsTable>) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&table)?;
+        Self::create_and_seek_to_first_with_options(table, ReadOptions::default())
+    }
+
+    /// Create a new iterator reading with `options`, and seek to the first key-value pair.
+    pub fn create_and_seek_to_first_with_options(
+        table: Arc<SsTable>,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        let mut readahead = Readahead::default();
+        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&table, &options, &mut readahead)?;
         let iter = Self {
             blk_iter,
             table,
             blk_idx,
+            options,
+            readahead,
         };
         Ok(iter)
     }
 
     /// Seek to the first key-value pair.
     pub fn seek_to_first(&mut self) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&self.table)?;
+        let (blk_idx, blk_iter) =
+            Self::seek_to_first_inner(&self.table, &self.options, &mut self.readahead)?;
         self.blk_idx = blk_idx;
         self.blk_iter = blk_iter;
         Ok(())
     }
 
-    fn seek_to_key_inner(table: &Arc<SsTable>, key: &[u8]) -> Result<(usize, BlockIterator)> {
+    fn seek_to_key_inner(
+        table: &Arc<SsTable>,
+        key: &[u8],
+        options: &ReadOptions,
+        readahead: &mut Readahead,
+    ) -> Result<(usize, BlockIterator)> {
         let mut blk_idx = table.find_block_idx(key);
-        let mut blk_iter =
-            BlockIterator::create_and_seek_to_key(table.read_block_cached(blk_idx)?, key);
+        let mut blk_iter = BlockIterator::create_and_seek_to_key(
+            table.read_block_for_iter(blk_idx, options, readahead)?,
+            key,
+        );
         if !blk_iter.is_valid() {
             blk_idx += 1;
             if blk_idx < table.num_of_blocks() {
-                blk_iter =
-                    BlockIterator::create_and_seek_to_first(table.read_block_cached(blk_idx)?);
+                blk_iter = BlockIterator::create_and_seek_to_first(
+                    table.read_block_for_iter(blk_idx, options, readahead)?,
+                );
             }
         }
         Ok((blk_idx, blk_iter))
@@ -56,18 +85,32 @@ impl SsTableIterator {
 
     /// Create a new iterator and seek to the first key-value pair which >= `key`.
     pub fn create_and_seek_to_key(table: Arc<SsTable>, key: &[u8]) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&table, key)?;
+        Self::create_and_seek_to_key_with_options(table, key, ReadOptions::default())
+    }
+
+    /// Create a new iterator reading with `options`, and seek to the first key-value pair which
+    /// >= `key`.
+    pub fn create_and_seek_to_key_with_options(
+        table: Arc<SsTable>,
+        key: &[u8],
+        options: ReadOptions,
+    ) -> Result<Self> {
+        let mut readahead = Readahead::default();
+        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&table, key, &options, &mut readahead)?;
         let iter = Self {
             blk_iter,
             table,
             blk_idx,
+            options,
+            readahead,
         };
         Ok(iter)
     }
 
     /// Seek to the first key-value pair which >= `key`.
     pub fn seek_to_key(&mut self, key: &[u8]) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&self.table, key)?;
+        let (blk_idx, blk_iter) =
+            Self::seek_to_key_inner(&self.table, key, &self.options, &mut self.readahead)?;
         self.blk_iter = blk_iter;
         self.blk_idx = blk_idx;
         Ok(())
@@ -87,14 +130,21 @@ impl StorageIterator for SsTableIterator {
         self.blk_iter.is_valid()
     }
 
+    fn meta(&self) -> EntryMeta {
+        self.blk_iter.meta()
+    }
+
     fn next(&mut self) -> Result<()> {
         self.blk_iter.next();
         if !self.blk_iter.is_valid() {
             self.blk_idx += 1;
             if self.blk_idx < self.table.num_of_blocks() {
-                self.blk_iter = BlockIterator::create_and_seek_to_first(
-                    self.table.read_block_cached(self.blk_idx)?,
-                );
+                self.blk_iter =
+                    BlockIterator::create_and_seek_to_first(self.table.read_block_for_iter(
+                        self.blk_idx,
+                        &self.options,
+                        &mut self.readahead,
+                    )?);
             }
         }
         Ok(())
@@ -0,0 +1,257 @@
+//! Reading ahead of iterators that read the blocks of a table in order, so that long scans make
+//! a few large reads instead of one per block.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+
+use super::SsTable;
+use crate::block::Block;
+use crate::read_options::ReadOptions;
+
+/// Size of the first automatic readahead of an iterator. It doubles with every refill, up to
+/// `ReadOptions::max_auto_readahead_size`.
+const INITIAL_AUTO_READAHEAD_SIZE: usize = 8 * 1024;
+
+/// Automatic readahead starts once an iterator has read this many blocks in a row after the
+/// first one.
+const SEQUENTIAL_READS_FOR_READAHEAD: usize = 2;
+
+/// Whole blocks read at once, starting at `offset` in the file.
+#[derive(Default)]
+pub(crate) struct Window {
+    pub(crate) offset: usize,
+    pub(crate) data: Bytes,
+}
+
+impl Window {
+    fn contains(&self, offset: usize, offset_end: usize) -> bool {
+        offset >= self.offset && offset_end <= self.offset + self.data.len()
+    }
+}
+
+/// A background thread reading the windows of one iterator ahead of it, one at a time.
+pub(crate) struct Prefetcher {
+    /// Reads to do, as the generation they belong to and the range of the file to read.
+    requests: Option<Sender<(usize, usize, usize)>>,
+    /// Data read, with the generation of the read.
+    results: Receiver<(usize, Result<Bytes>)>,
+    /// Incremented to cancel the reads requested so far. Reads of an older generation are
+    /// skipped if they have not started yet, and their data is discarded otherwise.
+    generation: Arc<AtomicUsize>,
+    /// The generation and offset of the read whose data has not been received yet.
+    pub(crate) pending: Option<(usize, usize)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Prefetcher {
+    fn new(table: Arc<SsTable>) -> Self {
+        let (requests, worker_requests) = mpsc::channel::<(usize, usize, usize)>();
+        let (worker_results, results) = mpsc::channel();
+        let generation = Arc::new(AtomicUsize::new(0));
+        let worker_generation = generation.clone();
+        let worker = std::thread::spawn(move || {
+            for (generation, offset, len) in worker_requests {
+                if generation != worker_generation.load(Ordering::Acquire) {
+                    continue;
+                }
+                let data = table.file.read(offset as u64, len as u64);
+                if worker_results.send((generation, data)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            requests: Some(requests),
+            results,
+            generation,
+            pending: None,
+            worker: Some(worker),
+        }
+    }
+
+    /// Start reading `len` bytes at `offset`, cancelling the read in progress if any.
+    fn start(&mut self, offset: usize, len: usize) {
+        self.cancel();
+        let generation = self.generation.load(Ordering::Acquire);
+        if let Some(requests) = &self.requests {
+            if requests.send((generation, offset, len)).is_ok() {
+                self.pending = Some((generation, offset));
+            }
+        }
+    }
+
+    /// Cancel the read in progress, if any.
+    fn cancel(&mut self) {
+        if self.pending.take().is_some() {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Wait for the data read at `offset`, if that is the read in progress.
+    fn take(&mut self, offset: usize) -> Result<Option<Bytes>> {
+        match self.pending {
+            Some((generation, pending_offset)) if pending_offset == offset => {
+                self.pending = None;
+                loop {
+                    let (result_generation, data) = self
+                        .results
+                        .recv()
+                        .map_err(|_| anyhow!("prefetch thread panicked"))?;
+                    if result_generation == generation {
+                        return data.map(Some);
+                    }
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Drop for Prefetcher {
+    /// Stop the thread, waiting for the read in progress if any.
+    fn drop(&mut self) {
+        self.cancel();
+        self.requests = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The readahead state of an iterator.
+#[derive(Default)]
+pub(crate) struct Readahead {
+    pub(crate) window: Window,
+    /// Reads the window after the current one, once the iterator prefetches.
+    pub(crate) prefetcher: Option<Prefetcher>,
+    /// The block accessed last, to detect sequential reads.
+    last_block_idx: Option<usize>,
+    sequential_reads: usize,
+    /// Size of the next automatic readahead.
+    auto_size: usize,
+}
+
+impl Readahead {
+    /// Track an access to `block_idx`. A jump to another block stops the automatic readahead
+    /// until the iterator reads in order again.
+    fn on_access(&mut self, block_idx: usize, options: &ReadOptions) {
+        let sequential = block_idx > 0 && self.last_block_idx == Some(block_idx - 1);
+        if sequential {
+            self.sequential_reads += 1;
+        } else {
+            self.sequential_reads = 0;
+            self.auto_size = INITIAL_AUTO_READAHEAD_SIZE.min(options.max_auto_readahead_size);
+            if let Some(prefetcher) = &mut self.prefetcher {
+                prefetcher.cancel();
+            }
+        }
+        self.last_block_idx = Some(block_idx);
+    }
+
+    /// Get how many bytes to read at once when the next block is not in the window, or 0 to
+    /// read it alone.
+    fn size(&self, options: &ReadOptions) -> usize {
+        if options.readahead_size > 0 {
+            options.readahead_size
+        } else if self.sequential_reads >= SEQUENTIAL_READS_FOR_READAHEAD {
+            self.auto_size
+        } else {
+            0
+        }
+    }
+}
+
+impl SsTable {
+    /// Read a block with block cache as an iterator reading with `options`. Blocks missing from
+    /// the cache are read through the readahead window of the iterator, which is refilled with
+    /// `options.readahead_size` bytes, or a growing size once the iterator reads in order.
+    pub(crate) fn read_block_for_iter(
+        self: &Arc<Self>,
+        block_idx: usize,
+        options: &ReadOptions,
+        readahead: &mut Readahead,
+    ) -> Result<Arc<Block>> {
+        readahead.on_access(block_idx, options);
+        let (offset, offset_end) = self.block_range(block_idx);
+        if readahead.size(options) == 0 && !readahead.window.contains(offset, offset_end) {
+            return self.read_block_cached_with_options(block_idx, options);
+        }
+        self.read_block_through_cache(block_idx, options, || {
+            let block_data = self.read_through_window(block_idx, options, readahead)?;
+            self.decode_block(block_idx, block_data, options)
+        })
+    }
+
+    /// Get the data of a block from the readahead window, refilling it first if needed.
+    fn read_through_window(
+        self: &Arc<Self>,
+        block_idx: usize,
+        options: &ReadOptions,
+        readahead: &mut Readahead,
+    ) -> Result<Bytes> {
+        let (offset, offset_end) = self.block_range(block_idx);
+        if !readahead.window.contains(offset, offset_end) {
+            let prefetched = match &mut readahead.prefetcher {
+                Some(prefetcher) => prefetcher.take(offset)?,
+                None => None,
+            };
+            let data = match prefetched {
+                Some(data) if data.len() >= offset_end - offset => data,
+                _ => {
+                    let read_end = self.readahead_end(block_idx, readahead.size(options));
+                    self.file.read(offset as u64, (read_end - offset) as u64)?
+                }
+            };
+            options.count(|perf| &perf.block_bytes_read, data.len() as u64);
+            readahead.window = Window { offset, data };
+            if options.readahead_size == 0 {
+                readahead.auto_size =
+                    (readahead.auto_size * 2).min(options.max_auto_readahead_size);
+            }
+            if options.async_prefetch {
+                self.start_prefetch(options, readahead);
+            }
+        }
+        let start = offset - readahead.window.offset;
+        Ok(readahead
+            .window
+            .data
+            .slice(start..start + offset_end - offset))
+    }
+
+    /// Start reading the window after the current one on the prefetch thread of the iterator.
+    fn start_prefetch(self: &Arc<Self>, options: &ReadOptions, readahead: &mut Readahead) {
+        let offset = readahead.window.offset + readahead.window.data.len();
+        let block_idx = self
+            .block_metas
+            .partition_point(|meta| meta.offset < offset);
+        let size = readahead.size(options);
+        if block_idx >= self.block_metas.len() || size == 0 {
+            return;
+        }
+        let read_end = self.readahead_end(block_idx, size);
+        let prefetcher = readahead
+            .prefetcher
+            .get_or_insert_with(|| Prefetcher::new(self.clone()));
+        prefetcher.start(offset, read_end - offset);
+    }
+
+    /// Get the end of the whole blocks, starting with `block_idx`, that fit in `size` bytes.
+    /// The first block is always included.
+    fn readahead_end(&self, block_idx: usize, size: usize) -> usize {
+        let (offset, mut read_end) = self.block_range(block_idx);
+        for next_idx in block_idx + 1..self.block_metas.len() {
+            let (_, next_end) = self.block_range(next_idx);
+            if next_end - offset > size {
+                break;
+            }
+            read_end = next_end;
+        }
+        read_end
+    }
+}
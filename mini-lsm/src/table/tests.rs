@@ -1,10 +1,14 @@
+use std::path::Path;
 use std::sync::Arc;
 
 use bytes::Bytes;
 use tempfile::{tempdir, TempDir};
 
+use super::compression::ZSTD_DICTIONARY_CODEC;
 use super::*;
+use crate::backend::{FileMode, LocalBackend, MemoryBackend};
 use crate::iterators::StorageIterator;
+use crate::read_options::ReadOptions;
 use crate::table::SsTableBuilder;
 
 #[test]
@@ -41,8 +45,17 @@ fn num_of_keys() -> usize {
     100
 }
 
-fn generate_sst() -> (TempDir, SsTable) {
+fn backends() -> Vec<Arc<dyn StorageBackend>> {
+    vec![
+        Arc::new(LocalBackend::new(FileMode::Read)),
+        Arc::new(LocalBackend::new(FileMode::Mmap)),
+        Arc::new(MemoryBackend::new()),
+    ]
+}
+
+fn generate_sst(backend: Arc<dyn StorageBackend>) -> (TempDir, SsTable) {
     let mut builder = SsTableBuilder::new(128);
+    builder.set_backend(backend);
     for idx in 0..num_of_keys() {
         let key = key_of(idx);
         let value = value_of(idx);
@@ -55,15 +68,32 @@ fn generate_sst() -> (TempDir, SsTable) {
 
 #[test]
 fn test_sst_build_all() {
-    generate_sst();
+    for backend in backends() {
+        generate_sst(backend.clone());
+    }
 }
 
 #[test]
 fn test_sst_decode() {
-    let (_dir, sst) = generate_sst();
-    let meta = sst.block_metas.clone();
-    let new_sst = SsTable::open_for_test(sst.file).unwrap();
-    assert_eq!(new_sst.block_metas, meta);
+    for backend in backends() {
+        let (_dir, sst) = generate_sst(backend.clone());
+        let meta = sst.block_metas.clone();
+        let new_sst = SsTable::open_for_test(sst.file).unwrap();
+        assert_eq!(new_sst.block_metas, meta);
+    }
+}
+
+#[test]
+fn test_sst_reopen() {
+    for backend in backends() {
+        let (dir, sst) = generate_sst(backend.clone());
+        let meta = sst.block_metas.clone();
+        drop(sst);
+        let file =
+            FileObject::open_with_backend(backend.as_ref(), &dir.path().join("1.sst")).unwrap();
+        let new_sst = SsTable::open_for_test(file).unwrap();
+        assert_eq!(new_sst.block_metas, meta);
+    }
 }
 
 fn as_bytes(x: &[u8]) -> Bytes {
@@ -72,59 +102,437 @@ fn as_bytes(x: &[u8]) -> Bytes {
 
 #[test]
 fn test_sst_iterator() {
-    let (_dir, sst) = generate_sst();
-    let sst = Arc::new(sst);
-    let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
-    for _ in 0..5 {
-        for i in 0..num_of_keys() {
-            let key = iter.key();
-            let value = iter.value();
-            assert_eq!(
-                key,
-                key_of(i),
-                "expected key: {:?}, actual key: {:?}",
-                as_bytes(&key_of(i)),
-                as_bytes(key)
-            );
-            assert_eq!(
-                value,
-                value_of(i),
-                "expected value: {:?}, actual value: {:?}",
-                as_bytes(&value_of(i)),
-                as_bytes(value)
-            );
-            iter.next().unwrap();
+    for backend in backends() {
+        let (_dir, sst) = generate_sst(backend.clone());
+        let sst = Arc::new(sst);
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+        for _ in 0..5 {
+            for i in 0..num_of_keys() {
+                let key = iter.key();
+                let value = iter.value();
+                assert_eq!(
+                    key,
+                    key_of(i),
+                    "expected key: {:?}, actual key: {:?}",
+                    as_bytes(&key_of(i)),
+                    as_bytes(key)
+                );
+                assert_eq!(
+                    value,
+                    value_of(i),
+                    "expected value: {:?}, actual value: {:?}",
+                    as_bytes(&value_of(i)),
+                    as_bytes(value)
+                );
+                iter.next().unwrap();
+            }
+            iter.seek_to_first().unwrap();
         }
-        iter.seek_to_first().unwrap();
     }
 }
 
 #[test]
 fn test_sst_seek_key() {
-    let (_dir, sst) = generate_sst();
-    let sst = Arc::new(sst);
-    let mut iter = SsTableIterator::create_and_seek_to_key(sst, &key_of(0)).unwrap();
-    for offset in 1..=5 {
-        for i in 0..num_of_keys() {
-            let key = iter.key();
-            let value = iter.value();
-            assert_eq!(
-                key,
-                key_of(i),
-                "expected key: {:?}, actual key: {:?}",
-                as_bytes(&key_of(i)),
-                as_bytes(key)
+    for backend in backends() {
+        let (_dir, sst) = generate_sst(backend.clone());
+        let sst = Arc::new(sst);
+        let mut iter = SsTableIterator::create_and_seek_to_key(sst, &key_of(0)).unwrap();
+        for offset in 1..=5 {
+            for i in 0..num_of_keys() {
+                let key = iter.key();
+                let value = iter.value();
+                assert_eq!(
+                    key,
+                    key_of(i),
+                    "expected key: {:?}, actual key: {:?}",
+                    as_bytes(&key_of(i)),
+                    as_bytes(key)
+                );
+                assert_eq!(
+                    value,
+                    value_of(i),
+                    "expected value: {:?}, actual value: {:?}",
+                    as_bytes(&value_of(i)),
+                    as_bytes(value)
+                );
+                iter.seek_to_key(&format!("key_{:03}", i * 5 + offset).into_bytes())
+                    .unwrap();
+            }
+            iter.seek_to_key(b"k").unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_sst_build_streaming() {
+    let backend = Arc::new(MemoryBackend::new());
+    let dir = Path::new("/lsm");
+    let mut builder = SsTableBuilder::create(128, backend.clone(), dir.join("1.sst")).unwrap();
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    // Every finished block is already in the temporary file.
+    let tmp = backend.open(&dir.join("1.sst.tmp")).unwrap();
+    assert!(tmp.size() > 0);
+    assert_eq!(tmp.size() as usize, builder.estimated_size());
+    let sst = builder.build_for_test(dir.join("1.sst")).unwrap();
+    assert_eq!(backend.list(dir).unwrap(), vec![dir.join("1.sst")]);
+
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::new(sst)).unwrap();
+    for i in 0..num_of_keys() {
+        assert_eq!(iter.key(), key_of(i));
+        assert_eq!(iter.value(), value_of(i));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_sst_compression() {
+    let mut sizes = Vec::new();
+    for compression in [
+        CompressionType::None,
+        CompressionType::Snappy,
+        CompressionType::Lz4,
+        CompressionType::Zstd,
+    ] {
+        let dir = tempdir().unwrap();
+        let mut builder = SsTableBuilder::new(4096);
+        builder.set_compression(compression);
+        for idx in 0..num_of_keys() {
+            builder.add(
+                &key_of(idx),
+                &format!("{{\"value\": {:0100}}}", idx).into_bytes(),
             );
+        }
+        let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+        sizes.push(sst.file.size());
+
+        let file = FileObject::open(&dir.path().join("1.sst")).unwrap();
+        let sst = Arc::new(SsTable::open_for_test(file).unwrap());
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+        for idx in 0..num_of_keys() {
+            assert_eq!(iter.key(), key_of(idx));
             assert_eq!(
-                value,
-                value_of(i),
-                "expected value: {:?}, actual value: {:?}",
-                as_bytes(&value_of(i)),
-                as_bytes(value)
+                iter.value(),
+                format!("{{\"value\": {:0100}}}", idx).into_bytes()
             );
-            iter.seek_to_key(&format!("key_{:03}", i * 5 + offset).into_bytes())
-                .unwrap();
+            iter.next().unwrap();
         }
-        iter.seek_to_key(b"k").unwrap();
+        assert!(!iter.is_valid());
+    }
+    for size in &sizes[1..] {
+        assert!(*size < sizes[0], "compressed sizes: {:?}", sizes);
+    }
+}
+
+#[test]
+fn test_sst_incompressible_block_stored_raw() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(4096);
+    builder.set_compression(CompressionType::Zstd);
+    builder.add(b"k", b"v");
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let raw = sst.file.read(0, sst.block_meta_offset as u64).unwrap();
+    // The codec is followed by the checksum.
+    assert_eq!(raw[raw.len() - 5], CompressionType::None.to_u8());
+    let iter = SsTableIterator::create_and_seek_to_first(Arc::new(sst)).unwrap();
+    assert_eq!(iter.value(), b"v");
+}
+
+fn json_record_of(idx: usize) -> Vec<u8> {
+    format!(
+        "{{\"id\": {}, \"name\": \"user_{}\", \"email\": \"user_{}@example.com\", \"active\": {}}}",
+        idx,
+        idx,
+        idx,
+        ["true", "false"][idx % 2]
+    )
+    .into_bytes()
+}
+
+fn build_json_sst(path: &Path, dictionary_size: Option<usize>) -> SsTable {
+    let mut builder = SsTableBuilder::new(256);
+    builder.set_compression(CompressionType::Zstd);
+    if let Some(dictionary_size) = dictionary_size {
+        builder.set_zstd_dictionary(dictionary_size);
+    }
+    for idx in 0..4000 {
+        builder.add(format!("key_{:05}", idx).as_bytes(), &json_record_of(idx));
+    }
+    builder.build_for_test(path).unwrap()
+}
+
+#[test]
+fn test_sst_zstd_dictionary() {
+    let dir = tempdir().unwrap();
+    let plain = build_json_sst(&dir.path().join("1.sst"), None);
+    let sst = build_json_sst(&dir.path().join("2.sst"), Some(2048));
+    assert!(sst.dictionary.is_some());
+    let raw = sst.file.read(0, sst.block_metas[1].offset as u64).unwrap();
+    assert_eq!(raw[raw.len() - 5], ZSTD_DICTIONARY_CODEC);
+    assert!(
+        sst.file.size() < plain.file.size(),
+        "with dictionary: {}, without: {}",
+        sst.file.size(),
+        plain.file.size()
+    );
+
+    let file = FileObject::open(&dir.path().join("2.sst")).unwrap();
+    let sst = Arc::new(SsTable::open_for_test(file).unwrap());
+    assert!(sst.dictionary.is_some());
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+    for idx in 0..4000 {
+        assert_eq!(iter.key(), format!("key_{:05}", idx).as_bytes());
+        assert_eq!(iter.value(), json_record_of(idx));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_sst_zstd_dictionary_too_few_samples() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(4096);
+    builder.set_compression(CompressionType::Zstd);
+    builder.set_zstd_dictionary(2048);
+    builder.add(b"k", &json_record_of(0));
+    builder.build_for_test(dir.path().join("1.sst")).unwrap();
+
+    let file = FileObject::open(&dir.path().join("1.sst")).unwrap();
+    let sst = Arc::new(SsTable::open_for_test(file).unwrap());
+    assert!(sst.dictionary.is_none());
+    let iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+    assert_eq!(iter.value(), json_record_of(0));
+}
+
+#[test]
+fn test_sst_zstd_dictionary_bounded_samples() {
+    let mut builder = SsTableBuilder::new(4096);
+    // 100 times the dictionary size would be 6.4 MiB of samples.
+    builder.set_zstd_dictionary(64 << 10);
+    let mut added = 0;
+    while builder.meta.is_empty() {
+        let key = format!("key_{:07}", added);
+        let value = json_record_of(added);
+        builder.add(key.as_bytes(), &value);
+        added += key.len() + value.len();
+    }
+    // The dictionary was trained once 4 MiB of encoded blocks were held back.
+    assert!(builder.samples.is_empty());
+    assert!(added > 3 << 20);
+    assert!(added <= 4 << 20);
+}
+
+#[test]
+fn test_sst_checksum_mismatch() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let sst = builder.build_for_test(&path).unwrap();
+    let offset = sst.block_metas[1].offset + 10;
+    drop(sst);
+    let mut data = std::fs::read(&path).unwrap();
+    data[offset] ^= 0xff;
+    std::fs::write(&path, data).unwrap();
+
+    let sst = SsTable::open(0, None, FileObject::open(&path).unwrap()).unwrap();
+    assert!(sst.read_block(0).is_ok());
+    let err = match sst.read_block(1) {
+        Ok(_) => panic!("corrupted block read successfully"),
+        Err(e) => e,
+    };
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+    let options = ReadOptions {
+        verify_checksums: false,
+        ..Default::default()
+    };
+    assert!(sst.read_block_with_options(1, &options).is_ok());
+
+    // The unverified block is not cached, so later reads still check it.
+    let block_cache = Arc::new(BlockCache::new(1024));
+    let sst = SsTable::open(0, Some(block_cache), FileObject::open(&path).unwrap()).unwrap();
+    assert!(sst.read_block_cached_with_options(1, &options).is_ok());
+    assert!(sst.read_block_cached(1).is_err());
+}
+
+#[test]
+fn test_sst_fill_cache() {
+    let dir = tempdir().unwrap();
+    let block_cache = Arc::new(BlockCache::new(1024));
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let sst = Arc::new(
+        builder
+            .build(1, Some(block_cache.clone()), dir.path().join("1.sst"))
+            .unwrap(),
+    );
+    let options = ReadOptions {
+        fill_cache: false,
+        ..Default::default()
+    };
+    let mut iter =
+        SsTableIterator::create_and_seek_to_first_with_options(sst.clone(), options).unwrap();
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    assert_eq!(block_cache.hits(), 0);
+    assert_eq!(block_cache.misses() as usize, sst.num_of_blocks());
+
+    // A cached block is still used.
+    sst.read_block_cached(0).unwrap();
+    let options = ReadOptions {
+        fill_cache: false,
+        ..Default::default()
+    };
+    SsTableIterator::create_and_seek_to_first_with_options(sst, options).unwrap();
+    assert_eq!(block_cache.hits(), 1);
+}
+
+#[test]
+fn test_sst_readahead() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..num_of_keys() {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    let sst = Arc::new(builder.build_for_test(dir.path().join("1.sst")).unwrap());
+    let options = ReadOptions {
+        readahead_size: 1000,
+        ..Default::default()
+    };
+    let mut readahead = Readahead::default();
+    sst.read_block_for_iter(0, &options, &mut readahead)
+        .unwrap();
+    // Only whole blocks are read ahead.
+    assert_eq!(readahead.window.offset, 0);
+    assert!(readahead.window.data.len() <= 1000);
+    let blocks = sst
+        .block_metas
+        .partition_point(|meta| meta.offset < readahead.window.data.len());
+    assert!(blocks > 1);
+    assert_eq!(sst.block_range(blocks - 1).1, readahead.window.data.len());
+    // The next blocks come from the buffer, until it is used up.
+    let data = readahead.window.data.clone();
+    sst.read_block_for_iter(blocks - 1, &options, &mut readahead)
+        .unwrap();
+    assert_eq!(readahead.window.data, data);
+    sst.read_block_for_iter(blocks, &options, &mut readahead)
+        .unwrap();
+    assert_eq!(readahead.window.offset, sst.block_metas[blocks].offset);
+
+    let mut iter = SsTableIterator::create_and_seek_to_first_with_options(sst, options).unwrap();
+    for idx in 0..num_of_keys() {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx));
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+fn build_large_sst(dir: &TempDir) -> Arc<SsTable> {
+    let mut builder = SsTableBuilder::new(128);
+    for idx in 0..2000 {
+        builder.add(&key_of(idx), &value_of(idx));
+    }
+    Arc::new(builder.build_for_test(dir.path().join("1.sst")).unwrap())
+}
+
+#[test]
+fn test_sst_auto_readahead() {
+    let dir = tempdir().unwrap();
+    let sst = build_large_sst(&dir);
+    let options = ReadOptions::default();
+    let mut readahead = Readahead::default();
+    // The first blocks are read one at a time.
+    for block_idx in 0..2 {
+        sst.read_block_for_iter(block_idx, &options, &mut readahead)
+            .unwrap();
+        assert!(readahead.window.data.is_empty());
+    }
+    // Then the readahead starts, and grows with every refill.
+    sst.read_block_for_iter(2, &options, &mut readahead)
+        .unwrap();
+    assert_eq!(readahead.window.offset, sst.block_metas[2].offset);
+    let first_size = readahead.window.data.len();
+    assert!(first_size > sst.block_range(2).1 - sst.block_range(2).0);
+    assert!(first_size <= 8 * 1024);
+    let mut block_idx = 3;
+    while readahead.window.offset == sst.block_metas[2].offset {
+        sst.read_block_for_iter(block_idx, &options, &mut readahead)
+            .unwrap();
+        block_idx += 1;
+    }
+    assert!(readahead.window.data.len() > first_size);
+    assert!(readahead.window.data.len() <= 16 * 1024);
+
+    // Reads in order again after a jump start over with single blocks.
+    let window_offset = readahead.window.offset;
+    sst.read_block_for_iter(0, &options, &mut readahead)
+        .unwrap();
+    sst.read_block_for_iter(1, &options, &mut readahead)
+        .unwrap();
+    assert_eq!(readahead.window.offset, window_offset);
+
+    let options = ReadOptions {
+        max_auto_readahead_size: 0,
+        ..Default::default()
+    };
+    let mut readahead = Readahead::default();
+    for block_idx in 0..sst.num_of_blocks() {
+        sst.read_block_for_iter(block_idx, &options, &mut readahead)
+            .unwrap();
+    }
+    assert!(readahead.window.data.is_empty());
+}
+
+#[test]
+fn test_sst_async_prefetch() {
+    let dir = tempdir().unwrap();
+    let sst = build_large_sst(&dir);
+    let options = ReadOptions {
+        readahead_size: 1000,
+        async_prefetch: true,
+        ..Default::default()
+    };
+    let mut readahead = Readahead::default();
+    sst.read_block_for_iter(0, &options, &mut readahead)
+        .unwrap();
+    let window_end = readahead.window.data.len();
+    let pending = |readahead: &Readahead| readahead.prefetcher.as_ref().unwrap().pending;
+    assert_eq!(pending(&readahead).unwrap().1, window_end);
+    // The next window is the prefetched one.
+    let next_idx = sst
+        .block_metas
+        .partition_point(|meta| meta.offset < window_end);
+    for block_idx in 1..=next_idx {
+        sst.read_block_for_iter(block_idx, &options, &mut readahead)
+            .unwrap();
+    }
+    assert_eq!(readahead.window.offset, window_end);
+    assert!(pending(&readahead).unwrap().1 > window_end);
+
+    // Jumping back cancels the prefetch for the old window in favor of the one after the new
+    // window, and dropping the iterator state stops the thread, which releases the table.
+    sst.read_block_for_iter(0, &options, &mut readahead)
+        .unwrap();
+    assert_eq!(pending(&readahead).unwrap().1, window_end);
+    drop(readahead);
+    assert_eq!(Arc::strong_count(&sst), 1);
+
+    let mut iter =
+        SsTableIterator::create_and_seek_to_first_with_options(sst.clone(), options).unwrap();
+    for idx in 0..2000 {
+        assert_eq!(iter.key(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx));
+        iter.next().unwrap();
     }
+    assert!(!iter.is_valid());
+    drop(iter);
+    assert_eq!(Arc::strong_count(&sst), 1);
 }
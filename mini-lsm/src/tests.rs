@@ -1 +1,15 @@
+mod blob_tests;
+mod column_family_tests;
+mod compaction_tests;
+mod crash_tests;
 pub mod day4_tests;
+mod event_listener_tests;
+mod metrics_tests;
+mod multi_get_tests;
+mod perf_context_tests;
+mod rate_limit_tests;
+mod stats_tests;
+#[cfg(feature = "tracing")]
+mod tracing_tests;
+mod ttl_tests;
+mod write_stall_tests;
@@ -0,0 +1,177 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+
+const DIR: &str = "/lsm";
+
+fn open_storage(backend: &Arc<MemoryBackend>) -> LsmStorage {
+    LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: backend.clone(),
+            min_blob_size: 1024,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn large_value(idx: usize, version: usize) -> Vec<u8> {
+    format!("value_{}_{}_", idx, version)
+        .repeat(4000)
+        .into_bytes()
+}
+
+fn blob_files(backend: &MemoryBackend) -> Vec<String> {
+    backend
+        .list(Path::new(DIR))
+        .unwrap()
+        .into_iter()
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("blob"))
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+        .collect()
+}
+
+fn check_scan(storage: &LsmStorage, expected: &[(Vec<u8>, Vec<u8>)]) {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for (key, value) in expected {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), &key[..]);
+        assert_eq!(iter.value(), &value[..]);
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_large_values_separated_on_flush() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    let mut expected = Vec::new();
+    for idx in 0..20 {
+        let key = format!("key_{:03}", idx).into_bytes();
+        let value = if idx % 3 == 0 {
+            format!("small_{}", idx).into_bytes()
+        } else {
+            large_value(idx, 0)
+        };
+        storage.put(&key, &value).unwrap();
+        expected.push((key, value));
+    }
+    storage.sync().unwrap();
+    assert_eq!(blob_files(&backend), vec!["00001.blob"]);
+
+    // The SST only holds pointers, so it is much smaller than the large values.
+    let sst = backend.open(&Path::new(DIR).join("00001.sst")).unwrap();
+    assert!(sst.size() < 4096, "SST size: {}", sst.size());
+
+    for (key, value) in &expected {
+        assert_eq!(
+            storage.get(key).unwrap().unwrap(),
+            Bytes::from(value.clone())
+        );
+    }
+    assert!(storage.get(b"key_999").unwrap().is_none());
+    check_scan(&storage, &expected);
+}
+
+#[test]
+fn test_value_too_large_to_store_inline() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(storage.put(b"key", &vec![b'x'; 65536]).is_err());
+    // Anything that fits the 16-bit value length is stored inline.
+    let value = vec![b'x'; 65535];
+    storage.put(b"key", &value).unwrap();
+    storage.sync().unwrap();
+    assert_eq!(&storage.get(b"key").unwrap().unwrap()[..], &value[..]);
+}
+
+#[test]
+fn test_blob_gc() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    for idx in 0..10 {
+        storage
+            .put(format!("key_{:03}", idx).as_bytes(), &large_value(idx, 0))
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    // Overwrite most values and delete one, leaving only a few live values in the first file.
+    for idx in 0..7 {
+        storage
+            .put(format!("key_{:03}", idx).as_bytes(), &large_value(idx, 1))
+            .unwrap();
+    }
+    storage.delete(b"key_007").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(blob_files(&backend), vec!["00001.blob", "00002.blob"]);
+
+    storage.gc_blob_files().unwrap();
+    assert_eq!(blob_files(&backend), vec!["00002.blob", "00003.blob"]);
+    let mut expected = Vec::new();
+    for idx in 0..10 {
+        let version = if idx < 7 { 1 } else { 0 };
+        if idx != 7 {
+            expected.push((
+                format!("key_{:03}", idx).into_bytes(),
+                large_value(idx, version),
+            ));
+        }
+    }
+    for (key, value) in &expected {
+        assert_eq!(
+            storage.get(key).unwrap().unwrap(),
+            Bytes::from(value.clone())
+        );
+    }
+    assert!(storage.get(b"key_007").unwrap().is_none());
+    check_scan(&storage, &expected);
+
+    // Nothing is garbage any more.
+    storage.gc_blob_files().unwrap();
+    assert_eq!(blob_files(&backend), vec!["00002.blob", "00003.blob"]);
+
+    // A file without live values is deleted without being rewritten.
+    for idx in 8..10 {
+        storage
+            .delete(format!("key_{:03}", idx).as_bytes())
+            .unwrap();
+    }
+    storage.sync().unwrap();
+    storage.gc_blob_files().unwrap();
+    assert_eq!(blob_files(&backend), vec!["00002.blob"]);
+}
+
+#[test]
+fn test_scan_survives_blob_gc() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    storage.put(b"a", &large_value(0, 0)).unwrap();
+    storage.put(b"b", &large_value(1, 0)).unwrap();
+    storage.sync().unwrap();
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    storage.put(b"a", b"small").unwrap();
+    storage.put(b"b", b"small").unwrap();
+    storage.sync().unwrap();
+    storage.gc_blob_files().unwrap();
+    assert!(blob_files(&backend).is_empty());
+
+    // The iterator reads from the snapshot it was created on.
+    assert_eq!(iter.value(), &large_value(0, 0)[..]);
+    iter.next().unwrap();
+    assert_eq!(iter.value(), &large_value(1, 0)[..]);
+}
@@ -0,0 +1,193 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions, DEFAULT_COLUMN_FAMILY};
+use crate::write_batch::WriteBatch;
+
+const DIR: &str = "/lsm";
+
+fn open_storage(backend: &Arc<MemoryBackend>) -> LsmStorage {
+    LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: backend.clone(),
+            level0_compaction_trigger: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn scan_cf(storage: &LsmStorage, column_family: &str) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut iter = storage
+        .scan_cf(column_family, Bound::Unbounded, Bound::Unbounded)
+        .unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+fn file_names(backend: &dyn StorageBackend) -> Vec<String> {
+    backend
+        .list(Path::new(DIR))
+        .unwrap()
+        .into_iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_column_families_are_independent() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    assert_eq!(storage.column_families(), vec![DEFAULT_COLUMN_FAMILY]);
+    storage.create_column_family("users").unwrap();
+    assert!(storage.create_column_family("users").is_err());
+    assert_eq!(storage.column_families(), vec!["default", "users"]);
+
+    storage.put(b"key", b"default_value").unwrap();
+    storage.put_cf("users", b"key", b"users_value").unwrap();
+    storage.put_cf("users", b"other", b"users_other").unwrap();
+    for round in 0..3 {
+        assert_eq!(&storage.get(b"key").unwrap().unwrap()[..], b"default_value");
+        assert_eq!(
+            &storage.get_cf("users", b"key").unwrap().unwrap()[..],
+            b"users_value"
+        );
+        assert!(storage.get(b"other").unwrap().is_none());
+        assert_eq!(
+            scan_cf(&storage, "users")[..2],
+            [
+                (b"key".to_vec(), b"users_value".to_vec()),
+                (b"other".to_vec(), b"users_other".to_vec()),
+            ]
+        );
+        // Flushed to L0, then compacted into L1.
+        storage.put_cf("users", b"round", &[b'0' + round]).unwrap();
+        storage.sync().unwrap();
+    }
+
+    storage.delete_cf("users", b"key").unwrap();
+    assert!(storage.get_cf("users", b"key").unwrap().is_none());
+    assert_eq!(&storage.get(b"key").unwrap().unwrap()[..], b"default_value");
+
+    assert!(storage.get_cf("missing", b"key").is_err());
+    assert!(storage.put_cf("missing", b"key", b"value").is_err());
+}
+
+#[test]
+fn test_column_families_share_sst_ids() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    storage.create_column_family("users").unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put_cf("users", b"a", b"2").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(file_names(&*backend), vec!["00001.sst", "users.00002.sst"]);
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"1");
+    assert_eq!(&storage.get_cf("users", b"a").unwrap().unwrap()[..], b"2");
+}
+
+#[test]
+fn test_reopen_recovers_column_families() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    assert!(storage.create_column_family("").is_err());
+    assert!(storage.create_column_family("a.b").is_err());
+    storage.create_column_family("users").unwrap();
+    storage.create_column_family("empty").unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put_cf("users", b"a", b"2").unwrap();
+    storage.sync().unwrap();
+    storage.put_cf("users", b"b", b"3").unwrap();
+    storage.sync().unwrap();
+    drop(storage);
+
+    // Column families without any file are not recovered.
+    let storage = open_storage(&backend);
+    assert_eq!(
+        storage.column_families(),
+        vec![DEFAULT_COLUMN_FAMILY, "users"]
+    );
+    assert_eq!(
+        scan_cf(&storage, DEFAULT_COLUMN_FAMILY),
+        vec![(b"a".to_vec(), b"1".to_vec())]
+    );
+    assert_eq!(
+        scan_cf(&storage, "users"),
+        vec![
+            (b"a".to_vec(), b"2".to_vec()),
+            (b"b".to_vec(), b"3".to_vec())
+        ]
+    );
+    // New tables do not reuse the IDs of the recovered ones, which are 1 in the default column
+    // family and 4 in users, where 2 and 3 were compacted. Flushing 5 compacts it with 1 into 6.
+    storage.put(b"c", b"4").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(file_names(&*backend), vec!["00006.sst", "users.00004.sst"]);
+}
+
+#[test]
+fn test_write_batch_across_column_families() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    storage.create_column_family("index").unwrap();
+    storage.put(b"user_1", b"old").unwrap();
+    storage.put_cf("index", b"old_1", b"user_1").unwrap();
+
+    let mut batch = WriteBatch::new();
+    batch
+        .put(b"user_1", b"new")
+        .delete_cf("index", b"old_1")
+        .put_cf("index", b"new_1", b"user_1");
+    assert_eq!(batch.len(), 3);
+    storage.write(&batch).unwrap();
+    assert_eq!(&storage.get(b"user_1").unwrap().unwrap()[..], b"new");
+    assert!(storage.get_cf("index", b"old_1").unwrap().is_none());
+    assert_eq!(
+        &storage.get_cf("index", b"new_1").unwrap().unwrap()[..],
+        b"user_1"
+    );
+
+    // A batch with an invalid update is rejected as a whole.
+    let mut batch = WriteBatch::new();
+    batch
+        .put(b"user_2", b"value")
+        .put_cf("missing", b"key", b"value");
+    assert!(storage.write(&batch).is_err());
+    assert!(storage.get(b"user_2").unwrap().is_none());
+}
+
+#[test]
+fn test_multi_get_sees_whole_write_batches() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend);
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for idx in 0..2000 {
+                let value = format!("{:05}", idx);
+                let mut batch = WriteBatch::new();
+                batch
+                    .put(b"key_1", value.as_bytes())
+                    .put(b"key_2", value.as_bytes());
+                storage.write(&batch).unwrap();
+                if idx % 500 == 0 {
+                    storage.sync().unwrap();
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+        while !done.load(Ordering::SeqCst) {
+            let values = storage.multi_get(&[b"key_1", b"key_2"]).unwrap();
+            assert_eq!(values[0], values[1]);
+        }
+    });
+}
@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::compaction::{CompactionFilter, FilterDecision};
+use crate::event_listener::{CompactionJobInfo, EventListener, FlushJobInfo};
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::table::{CompressionType, FileObject, SsTable};
+
+const DIR: &str = "/lsm";
+
+fn open_storage(backend: &Arc<MemoryBackend>, trigger: usize) -> LsmStorage {
+    LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: backend.clone(),
+            level0_compaction_trigger: trigger,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn sst_files(backend: &MemoryBackend) -> Vec<String> {
+    backend
+        .list(Path::new(DIR))
+        .unwrap()
+        .into_iter()
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("sst"))
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+        .collect()
+}
+
+/// Check that the SSTs do not overlap each other, as they should once L0 is empty.
+fn check_no_overlap(backend: &MemoryBackend) {
+    let mut ranges = Vec::new();
+    for name in sst_files(backend) {
+        let path = Path::new(DIR).join(name);
+        let file = FileObject::open_with_backend(backend, &path).unwrap();
+        ranges.push(SsTable::open(0, None, file).unwrap().key_range().unwrap());
+    }
+    ranges.sort();
+    for pair in ranges.windows(2) {
+        assert!(
+            pair[0].1 < pair[1].0,
+            "{:?} overlaps {:?}",
+            pair[0],
+            pair[1]
+        );
+    }
+}
+
+#[test]
+fn test_compaction_merges_l0_into_l1() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend, 3);
+    let mut expected = BTreeMap::new();
+    for round in 0..7 {
+        for idx in 0..50 {
+            let key = Bytes::from(format!("key_{:03}", idx * 7 % 50 + round));
+            let value = Bytes::from(format!("value_{}_{}", round, idx));
+            storage.put(&key, &value).unwrap();
+            expected.insert(key, value);
+        }
+        let key = Bytes::from(format!("key_{:03}", round * 3));
+        storage.delete(&key).unwrap();
+        expected.remove(&key);
+        storage.sync().unwrap();
+    }
+    // Rounds 0-2 were compacted into table 4, which rounds 3-5 were compacted with into table 8.
+    // Round 6 is in L0.
+    assert_eq!(sst_files(&backend), vec!["00008.sst", "00009.sst"]);
+
+    for idx in 0..60 {
+        let key = Bytes::from(format!("key_{:03}", idx));
+        assert_eq!(storage.get(&key).unwrap().as_ref(), expected.get(&key));
+    }
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for (key, value) in &expected {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), &key[..]);
+        assert_eq!(iter.value(), &value[..]);
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_compaction_drops_tombstones() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend, 2);
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.sync().unwrap();
+    storage.delete(b"a").unwrap();
+    storage.delete(b"b").unwrap();
+    storage.sync().unwrap();
+    // Nothing is left to write to L1.
+    assert!(sst_files(&backend).is_empty());
+    assert!(storage.get(b"a").unwrap().is_none());
+    let iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert!(!iter.is_valid());
+}
+
+#[derive(Default)]
+struct JobRecorder {
+    flushes: Mutex<Vec<FlushJobInfo>>,
+    compactions: Mutex<Vec<CompactionJobInfo>>,
+}
+
+impl EventListener for JobRecorder {
+    fn on_flush_completed(&self, info: &FlushJobInfo) {
+        self.flushes.lock().push(info.clone());
+    }
+
+    fn on_compaction_completed(&self, info: &CompactionJobInfo) {
+        self.compactions.lock().push(info.clone());
+    }
+}
+
+#[test]
+fn test_subcompactions() {
+    let recorder = Arc::new(JobRecorder::default());
+    let storage = LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 2,
+            max_subcompactions: 4,
+            event_listeners: vec![recorder.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut expected = BTreeMap::new();
+    for round in 0..2 {
+        for idx in 0..1000 {
+            let key = Bytes::from(format!("key_{:04}", idx));
+            if round == 1 && idx % 10 == 0 {
+                storage.delete(&key).unwrap();
+                expected.remove(&key);
+            } else {
+                let value = Bytes::from(format!("value_{}_{}", round, idx));
+                storage.put(&key, &value).unwrap();
+                expected.insert(key, value);
+            }
+        }
+        storage.sync().unwrap();
+    }
+
+    // The compaction wrote one SST per key range, in key order.
+    let compactions = recorder.compactions.lock();
+    assert_eq!(compactions.len(), 1);
+    let outputs = &compactions[0].output_tables;
+    assert_eq!(outputs.len(), 4);
+    for pair in outputs.windows(2) {
+        assert!(pair[0].sst_id < pair[1].sst_id);
+        assert!(pair[0].last_key < pair[1].first_key);
+    }
+    assert_eq!(storage.stats().levels[1].num_files, 4);
+
+    for idx in 0..1000 {
+        let key = Bytes::from(format!("key_{:04}", idx));
+        assert_eq!(storage.get(&key).unwrap().as_ref(), expected.get(&key));
+    }
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for (key, value) in &expected {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), &key[..]);
+        assert_eq!(iter.value(), &value[..]);
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_target_file_size() {
+    let recorder = Arc::new(JobRecorder::default());
+    let storage = LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 1,
+            target_file_size: 4096,
+            event_listeners: vec![recorder.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let mut expected = BTreeMap::new();
+    for idx in 0..2000 {
+        let key = Bytes::from(format!("key_{:04}", idx));
+        let value = Bytes::from(format!("value_{}", idx));
+        storage.put(&key, &value).unwrap();
+        expected.insert(key, value);
+    }
+    storage.sync().unwrap();
+    // Both the flush and the compaction of its output were split into tables of about the
+    // target size, without overlapping keys.
+    let flushes = recorder.flushes.lock();
+    let compactions = recorder.compactions.lock();
+    for tables in [&flushes[0].tables, &compactions[0].output_tables] {
+        assert!(tables.len() > 1);
+        for pair in tables.windows(2) {
+            assert!(pair[0].last_key < pair[1].first_key);
+        }
+        // Tables grow a block at a time, so they may end up a block past the target.
+        assert!(tables.iter().all(|table| table.file_size <= 3 * 4096));
+    }
+    assert_eq!(
+        storage.stats().levels[1].num_files,
+        compactions[0].output_tables.len()
+    );
+
+    for (key, value) in &expected {
+        assert_eq!(storage.get(key).unwrap().as_ref(), Some(value));
+    }
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    for (key, value) in &expected {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), &key[..]);
+        assert_eq!(iter.value(), &value[..]);
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_target_file_size_with_dictionary() {
+    let recorder = Arc::new(JobRecorder::default());
+    let storage = LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            compression_per_level: vec![CompressionType::Zstd],
+            zstd_dictionary_size: 1024,
+            target_file_size: 4096,
+            event_listeners: vec![recorder.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..2000 {
+        let key = format!("key_{:04}", idx);
+        let value = format!("{{\"id\": {}, \"name\": \"user_{}\"}}", idx, idx);
+        storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+    }
+    storage.sync().unwrap();
+    // The blocks held back to train the dictionary count towards the target size.
+    let flushes = recorder.flushes.lock();
+    assert!(flushes[0].tables.len() > 1);
+    assert!(flushes[0]
+        .tables
+        .iter()
+        .all(|table| table.file_size <= 3 * 4096));
+}
+
+#[test]
+fn test_compact_range() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: backend.clone(),
+            level0_compaction_trigger: 0,
+            target_file_size: 4096,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let key_of = |idx: usize| Bytes::from(format!("key_{:04}", idx));
+    for idx in 0..2000 {
+        storage.put(&key_of(idx), b"value").unwrap();
+    }
+    storage.force_full_compaction().unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.levels[0].num_files, 0);
+    assert!(stats.levels[1].num_files > 4);
+    let l1_files = sst_files(&backend);
+
+    // Delete a range of keys, and another key far from it in a separate L0 table.
+    for idx in 500..600 {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.sync().unwrap();
+    storage.delete(&key_of(1400)).unwrap();
+    storage.sync().unwrap();
+    storage
+        .compact_range(
+            Bound::Included(&key_of(1350)),
+            Bound::Excluded(&key_of(1450)),
+        )
+        .unwrap();
+    // The range overlaps an L0 table, so all of them are merged, with the L1 tables holding keys
+    // from the first to the last of them, including those between the two L0 tables.
+    let stats = storage.stats();
+    assert_eq!(stats.levels[0].num_files, 0);
+    let files = sst_files(&backend);
+    let kept = l1_files.iter().filter(|file| files.contains(file)).count();
+    assert!(kept > 0 && kept < l1_files.len());
+    check_no_overlap(&backend);
+    for idx in 0..2000 {
+        let deleted = (500..600).contains(&idx) || idx == 1400;
+        assert_eq!(storage.get(&key_of(idx)).unwrap().is_none(), deleted);
+    }
+
+    // No table holds keys in the range.
+    storage.put(b"zzz", b"value").unwrap();
+    storage.sync().unwrap();
+    let files = sst_files(&backend);
+    storage
+        .compact_range(Bound::Included(b"a"), Bound::Excluded(b"b"))
+        .unwrap();
+    assert_eq!(sst_files(&backend), files);
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.stats().levels[0].num_files, 0);
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 2000 - 101 + 1);
+}
+
+#[test]
+fn test_compact_range_keeps_newer_l0_entries_after_reopen() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = open_storage(&backend, 0);
+    storage.put(b"a", b"v1").unwrap();
+    storage.put(b"m", b"v1").unwrap();
+    storage.sync().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.put(b"a", b"v2").unwrap();
+    storage.sync().unwrap();
+    // Only the L1 table overlaps the range, but it spans the key of the L0 table, which must be
+    // merged too, as the output gets a newer ID than that table.
+    storage
+        .compact_range(Bound::Included(b"l"), Bound::Included(b"m"))
+        .unwrap();
+    assert_eq!(storage.stats().levels[0].num_files, 0);
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"v2");
+    drop(storage);
+
+    let storage = open_storage(&backend, 0);
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"v2");
+    assert_eq!(&storage.get(b"m").unwrap().unwrap()[..], b"v1");
+}
+
+/// Removes the keys of deleted tenants and upgrades old records to the current schema.
+struct TenantFilter;
+
+impl CompactionFilter for TenantFilter {
+    fn filter(&self, level: usize, key: &[u8], value: &[u8]) -> FilterDecision {
+        assert_eq!(level, 1);
+        if key.starts_with(b"deleted_tenant/") {
+            FilterDecision::Remove
+        } else if let Some(rest) = value.strip_prefix(b"v1:") {
+            FilterDecision::ChangeValue(Bytes::from([b"v2:", rest].concat()))
+        } else {
+            FilterDecision::Keep
+        }
+    }
+}
+
+#[test]
+fn test_compaction_filter() {
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend,
+            level0_compaction_trigger: 2,
+            min_blob_size: 1024,
+            compaction_filter: Some(Arc::new(TenantFilter)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let large = [&b"v1:"[..], &b"x".repeat(2000)].concat();
+    storage.put(b"deleted_tenant/a", b"v2:1").unwrap();
+    storage.put(b"deleted_tenant/b", &large).unwrap();
+    storage.put(b"tenant/a", b"v1:2").unwrap();
+    storage.put(b"tenant/b", &large).unwrap();
+    storage.sync().unwrap();
+    storage.put(b"tenant/c", b"v2:3").unwrap();
+    // The filter only runs on compaction.
+    assert_eq!(&storage.get(b"tenant/a").unwrap().unwrap()[..], b"v1:2");
+    storage.sync().unwrap();
+
+    assert!(storage.get(b"deleted_tenant/a").unwrap().is_none());
+    assert!(storage.get(b"deleted_tenant/b").unwrap().is_none());
+    assert_eq!(&storage.get(b"tenant/a").unwrap().unwrap()[..], b"v2:2");
+    assert_eq!(
+        storage.get(b"tenant/b").unwrap().unwrap(),
+        [&b"v2:"[..], &b"x".repeat(2000)].concat()
+    );
+    assert_eq!(&storage.get(b"tenant/c").unwrap().unwrap()[..], b"v2:3");
+}
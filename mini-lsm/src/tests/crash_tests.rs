@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::backend::fault::FaultInjectionBackend;
+use crate::backend::StorageBackend;
+use crate::event_listener::{CompactionJobInfo, EventListener};
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::write_batch::WriteBatch;
+
+const DIR: &str = "/lsm";
+
+fn open_storage(backend: &FaultInjectionBackend) -> LsmStorage {
+    open_storage_with_listeners(backend, Vec::new())
+}
+
+/// Open the storage, compacting L0 into L1 on every other flush.
+fn open_storage_with_listeners(
+    backend: &FaultInjectionBackend,
+    event_listeners: Vec<Arc<dyn EventListener>>,
+) -> LsmStorage {
+    LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: Arc::new(backend.clone()),
+            level0_compaction_trigger: 2,
+            event_listeners,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+/// Tracks whether a compaction is running.
+#[derive(Default)]
+struct CompactionTracker {
+    compacting: AtomicBool,
+}
+
+impl EventListener for CompactionTracker {
+    fn on_compaction_begin(&self, _info: &CompactionJobInfo) {
+        self.compacting.store(true, Ordering::SeqCst);
+    }
+
+    fn on_compaction_completed(&self, _info: &CompactionJobInfo) {
+        self.compacting.store(false, Ordering::SeqCst);
+    }
+}
+
+const NUM_KEYS: usize = 100;
+
+fn key_of(idx: usize) -> Bytes {
+    Bytes::from(format!("key_{:03}", idx))
+}
+
+/// Overwrite and delete random keys in rounds, and `sync` after each round, so that flushes and
+/// compactions run, until the engine is killed at a random point. Then crash the backend, reopen
+/// the storage on it, and check that every key has the value acknowledged by the last successful
+/// `sync`, or one written by the round that was interrupted. Returns whether the engine was
+/// killed in the middle of a compaction.
+fn check_crash_recovery(seed: u64, torn_writes: bool) -> bool {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let backend = FaultInjectionBackend::new(seed);
+    backend.set_torn_writes(torn_writes);
+    let tracker = Arc::new(CompactionTracker::default());
+    let storage = open_storage_with_listeners(&backend, vec![tracker.clone()]);
+    let mut acknowledged = BTreeMap::new();
+    let mut interrupted = BTreeMap::<Bytes, Vec<Option<Bytes>>>::new();
+    backend.kill_after(rng.gen_range(0..250));
+    for round in 0..20 {
+        let mut pending = BTreeMap::new();
+        for i in 0..rng.gen_range(1..50) {
+            let key = key_of(rng.gen_range(0..NUM_KEYS));
+            if rng.gen_range(0..5) == 0 {
+                storage.delete(&key).unwrap();
+                pending.insert(key, None);
+            } else {
+                let value = Bytes::from(format!("value_{}_{}_{}", seed, round, i));
+                storage.put(&key, &value).unwrap();
+                pending.insert(key, Some(value));
+            }
+        }
+        if storage.sync().is_err() {
+            assert!(backend.is_killed());
+            for (key, value) in pending {
+                interrupted.entry(key).or_default().push(value);
+            }
+            break;
+        }
+        acknowledged.extend(pending);
+    }
+    let killed_in_compaction = tracker.compacting.load(Ordering::SeqCst);
+    drop(storage);
+    backend.crash();
+
+    let storage = open_storage(&backend);
+    for idx in 0..NUM_KEYS {
+        let key = key_of(idx);
+        let recovered = storage.get(&key).unwrap();
+        let expected = acknowledged.get(&key).cloned().flatten();
+        assert!(
+            recovered == expected
+                || interrupted
+                    .get(&key)
+                    .into_iter()
+                    .flatten()
+                    .any(|value| *value == recovered),
+            "acknowledged write lost: seed={}, key={:?}, recovered={:?}, expected={:?}",
+            seed,
+            key,
+            recovered,
+            expected
+        );
+    }
+    // The reopened storage keeps working.
+    storage.put(b"key_new", b"value").unwrap();
+    storage.sync().unwrap();
+    drop(storage);
+    backend.crash();
+    let storage = open_storage(&backend);
+    assert_eq!(&storage.get(b"key_new").unwrap().unwrap()[..], b"value");
+    killed_in_compaction
+}
+
+#[test]
+fn test_crash_during_sync() {
+    let mut killed_in_compaction = 0;
+    for seed in 0..100 {
+        killed_in_compaction += check_crash_recovery(seed, false) as usize;
+    }
+    assert!(killed_in_compaction > 0);
+}
+
+#[test]
+fn test_crash_during_sync_torn_writes() {
+    let mut killed_in_compaction = 0;
+    for seed in 0..100 {
+        killed_in_compaction += check_crash_recovery(seed, true) as usize;
+    }
+    assert!(killed_in_compaction > 0);
+}
+
+#[test]
+fn test_injected_read_failure() {
+    let backend = FaultInjectionBackend::new(0);
+    let storage = open_storage(&backend);
+    storage.put(b"1", b"233").unwrap();
+    storage.sync().unwrap();
+    backend.set_fail_read(true);
+    assert!(storage.get(b"1").is_err());
+    backend.set_fail_read(false);
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+}
+
+#[test]
+fn test_unsynced_files_are_lost_on_crash() {
+    let backend = FaultInjectionBackend::new(0);
+    let dir = Path::new(DIR);
+    let mut file = backend.create(&dir.join("1.sst")).unwrap();
+    file.append(b"synced").unwrap();
+    file.sync().unwrap();
+    file.append(b"unsynced").unwrap();
+    backend.create(&dir.join("2.sst")).unwrap();
+    backend.sync_dir(dir).unwrap();
+    backend.create(&dir.join("3.sst")).unwrap();
+    backend.crash();
+
+    assert_eq!(
+        backend.list(dir).unwrap(),
+        vec![dir.join("1.sst"), dir.join("2.sst")]
+    );
+    let file = backend.open(&dir.join("1.sst")).unwrap();
+    assert_eq!(&file.read_at(0, file.size()).unwrap()[..], b"synced");
+}
+
+#[test]
+fn test_injected_sync_failure() {
+    let backend = FaultInjectionBackend::new(0);
+    let dir = Path::new(DIR);
+    let mut file = backend.create(&dir.join("1.sst")).unwrap();
+    file.append(b"data").unwrap();
+    backend.set_fail_sync(true);
+    assert!(file.sync().is_err());
+    assert!(backend.sync_dir(dir).is_err());
+    backend.set_fail_sync(false);
+    file.sync().unwrap();
+    backend.sync_dir(dir).unwrap();
+}
+
+#[test]
+fn test_sync_reports_fsync_failure() {
+    let backend = FaultInjectionBackend::new(0);
+    let storage = open_storage(&backend);
+    storage.put(b"1", b"233").unwrap();
+    backend.set_fail_sync(true);
+    assert!(storage.sync().is_err());
+    assert!(backend.list(Path::new(DIR)).unwrap().is_empty());
+    backend.set_fail_sync(false);
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    storage.sync().unwrap();
+    drop(storage);
+    backend.crash();
+    let storage = open_storage(&backend);
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+}
+
+/// Open the storage with tables of a few hundred bytes, so that flushes and compactions write
+/// many of them, and without compacting L0 on flushes.
+fn open_storage_with_small_tables(
+    backend: &FaultInjectionBackend,
+    max_subcompactions: usize,
+) -> LsmStorage {
+    LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: Arc::new(backend.clone()),
+            level0_compaction_trigger: 0,
+            target_file_size: 512,
+            max_subcompactions,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn put_keys(storage: &LsmStorage, num_keys: usize) {
+    for idx in 0..num_keys {
+        storage.put(&key_of(idx), b"value").unwrap();
+    }
+}
+
+/// Delete every key, and check that none is back once the storage is reopened.
+fn check_deleted_keys_stay_deleted(backend: &FaultInjectionBackend, num_keys: usize) {
+    let storage = open_storage_with_small_tables(backend, 1);
+    for idx in 0..num_keys {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.sync().unwrap();
+    storage.force_full_compaction().unwrap();
+    drop(storage);
+    let storage = open_storage_with_small_tables(backend, 1);
+    for idx in 0..num_keys {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), None);
+    }
+}
+
+#[test]
+fn test_failed_flush_leaves_no_tables() {
+    let backend = FaultInjectionBackend::new(0);
+    let storage = open_storage_with_small_tables(&backend, 1);
+    put_keys(&storage, 1000);
+    backend.set_fail_rename_after(Some(1));
+    assert!(storage.sync().is_err());
+    backend.set_fail_rename_after(None);
+    drop(storage);
+    assert!(backend.list(Path::new(DIR)).unwrap().is_empty());
+    check_deleted_keys_stay_deleted(&backend, 1000);
+}
+
+#[test]
+fn test_failed_compaction_leaves_no_tables() {
+    let backend = FaultInjectionBackend::new(0);
+    let storage = open_storage_with_small_tables(&backend, 4);
+    put_keys(&storage, 1000);
+    storage.sync().unwrap();
+    let files = backend.list(Path::new(DIR)).unwrap();
+    // Let some subcompactions finish, so that the others fail after them.
+    backend.set_fail_rename_after(Some(files.len() / 2));
+    assert!(storage.force_full_compaction().is_err());
+    backend.set_fail_rename_after(None);
+    drop(storage);
+    assert_eq!(backend.list(Path::new(DIR)).unwrap(), files);
+    check_deleted_keys_stay_deleted(&backend, 1000);
+}
+
+#[test]
+fn test_crash_never_splits_write_batch() {
+    // Kill the engine at every operation of the sync in turn, until it completes.
+    for ops in 0.. {
+        let backend = FaultInjectionBackend::new(ops as u64);
+        let storage = open_storage(&backend);
+        storage.create_column_family("users").unwrap();
+        storage.put_cf("users", b"a", b"0").unwrap();
+        storage.sync().unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"a", b"1").put_cf("users", b"a", b"1");
+        storage.write(&batch).unwrap();
+        backend.kill_after(ops);
+        let synced = storage.sync().is_ok();
+        drop(storage);
+        backend.crash();
+
+        let storage = open_storage(&backend);
+        let value = storage.get(b"a").unwrap();
+        let users_value = storage.get_cf("users", b"a").unwrap();
+        match value {
+            Some(value) => {
+                assert_eq!(&value[..], b"1");
+                assert_eq!(&users_value.unwrap()[..], b"1");
+            }
+            None => {
+                assert!(!synced);
+                assert_eq!(&users_value.unwrap()[..], b"0");
+            }
+        }
+        if synced {
+            break;
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::ops::Bound;
+use std::sync::Arc;
 
 use bytes::Bytes;
 use tempfile::tempdir;
@@ -185,3 +186,111 @@ fn test_storage_scan_memtable_2_after_sync() {
         vec![(Bytes::from("2"), Bytes::from("2333"))],
     );
 }
+
+#[test]
+fn test_storage_get_after_sync_mmap() {
+    use crate::backend::{FileMode, LocalBackend};
+    use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+    let dir = tempdir().unwrap();
+    let storage = LsmStorage::open_with_options(
+        &dir,
+        LsmStorageOptions {
+            backend: Arc::new(LocalBackend::new(FileMode::Mmap)),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.sync().unwrap();
+    storage.put(b"3", b"23333").unwrap();
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    assert_eq!(&storage.get(b"2").unwrap().unwrap()[..], b"2333");
+    assert_eq!(&storage.get(b"3").unwrap().unwrap()[..], b"23333");
+    storage.delete(b"2").unwrap();
+    assert!(storage.get(b"2").unwrap().is_none());
+}
+
+#[test]
+fn test_storage_get_after_sync_in_memory() {
+    use crate::backend::{MemoryBackend, StorageBackend};
+    use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+    let backend = Arc::new(MemoryBackend::new());
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: backend.clone(),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage.put(b"1", b"233").unwrap();
+    storage.put(b"2", b"2333").unwrap();
+    storage.sync().unwrap();
+    storage.put(b"3", b"23333").unwrap();
+    storage.sync().unwrap();
+    assert_eq!(backend.list("/lsm".as_ref()).unwrap().len(), 2);
+    assert_eq!(&storage.get(b"1").unwrap().unwrap()[..], b"233");
+    assert_eq!(&storage.get(b"2").unwrap().unwrap()[..], b"2333");
+    assert_eq!(&storage.get(b"3").unwrap().unwrap()[..], b"23333");
+}
+
+#[test]
+fn test_storage_get_after_sync_compressed() {
+    use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+    use crate::table::CompressionType;
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        compression_per_level: vec![CompressionType::Lz4, CompressionType::Zstd],
+        ..Default::default()
+    };
+    assert_eq!(options.compression_of_level(0), CompressionType::Lz4);
+    assert_eq!(options.compression_of_level(6), CompressionType::Zstd);
+    let storage = LsmStorage::open_with_options(&dir, options).unwrap();
+    for i in 0..1000 {
+        let key = format!("key_{:05}", i);
+        let value = format!("{{\"id\": {}, \"payload\": \"{:0>64}\"}}", i, i);
+        storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+    }
+    storage.sync().unwrap();
+    for i in 0..1000 {
+        let key = format!("key_{:05}", i);
+        let value = format!("{{\"id\": {}, \"payload\": \"{:0>64}\"}}", i, i);
+        assert_eq!(
+            &storage.get(key.as_bytes()).unwrap().unwrap()[..],
+            value.as_bytes()
+        );
+    }
+}
+
+#[test]
+fn test_storage_get_after_sync_zstd_dictionary() {
+    use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+    use crate::table::CompressionType;
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions {
+        compression_per_level: vec![CompressionType::Zstd],
+        zstd_dictionary_size: 512,
+        ..Default::default()
+    };
+    let storage = LsmStorage::open_with_options(&dir, options).unwrap();
+    for i in 0..1000 {
+        let key = format!("key_{:05}", i);
+        let value = format!("{{\"id\": {}, \"payload\": \"{:0>64}\"}}", i, i);
+        storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+    }
+    storage.sync().unwrap();
+    for i in 0..1000 {
+        let key = format!("key_{:05}", i);
+        let value = format!("{{\"id\": {}, \"payload\": \"{:0>64}\"}}", i, i);
+        assert_eq!(
+            &storage.get(key.as_bytes()).unwrap().unwrap()[..],
+            value.as_bytes()
+        );
+    }
+    // The flushed SST embeds the trained dictionary, which starts with the zstd dictionary magic.
+    let sst = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+    let data = std::fs::read(sst.path()).unwrap();
+    let magic = 0xEC30A437u32.to_le_bytes();
+    assert!(data.windows(4).any(|window| window == magic));
+}
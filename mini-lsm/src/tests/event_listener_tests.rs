@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::backend::MemoryBackend;
+use crate::event_listener::{
+    CompactionJobInfo, EventListener, FlushJobInfo, TableFileInfo, WriteStallInfo,
+};
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions, WriteStallCause};
+
+#[derive(Default)]
+struct RecordingListener {
+    events: Mutex<Vec<String>>,
+    created: Mutex<Vec<TableFileInfo>>,
+    compactions: Mutex<Vec<CompactionJobInfo>>,
+    stalls: Mutex<Vec<WriteStallInfo>>,
+}
+
+impl EventListener for RecordingListener {
+    fn on_flush_begin(&self, info: &FlushJobInfo) {
+        assert!(info.tables.is_empty());
+        self.events
+            .lock()
+            .push(format!("flush begin {}", info.sst_id));
+    }
+
+    fn on_flush_completed(&self, info: &FlushJobInfo) {
+        assert_eq!(info.tables[0].sst_id, info.sst_id);
+        self.events
+            .lock()
+            .push(format!("flush completed {}", info.sst_id));
+    }
+
+    fn on_compaction_begin(&self, info: &CompactionJobInfo) {
+        assert!(info.output_tables.is_empty());
+        self.events.lock().push("compaction begin".to_string());
+    }
+
+    fn on_compaction_completed(&self, info: &CompactionJobInfo) {
+        self.events.lock().push("compaction completed".to_string());
+        self.compactions.lock().push(info.clone());
+    }
+
+    fn on_table_file_created(&self, info: &TableFileInfo) {
+        self.events
+            .lock()
+            .push(format!("created {} L{}", info.sst_id, info.level));
+        self.created.lock().push(info.clone());
+    }
+
+    fn on_table_file_deleted(&self, info: &TableFileInfo) {
+        self.events
+            .lock()
+            .push(format!("deleted {} L{}", info.sst_id, info.level));
+    }
+
+    fn on_write_stall(&self, info: &WriteStallInfo) {
+        self.stalls.lock().push(*info);
+    }
+}
+
+#[test]
+fn test_flush_and_compaction_events() {
+    let listener = Arc::new(RecordingListener::default());
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 2,
+            event_listeners: vec![listener.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage.put(b"b", b"value").unwrap();
+    storage.put(b"d", b"value").unwrap();
+    storage.sync().unwrap();
+    storage.put(b"a", b"value").unwrap();
+    storage.put(b"c", b"value").unwrap();
+    storage.sync().unwrap();
+
+    assert_eq!(
+        *listener.events.lock(),
+        vec![
+            "flush begin 1",
+            "created 1 L0",
+            "flush completed 1",
+            "flush begin 2",
+            "created 2 L0",
+            "flush completed 2",
+            "compaction begin",
+            "created 3 L1",
+            "deleted 1 L0",
+            "deleted 2 L0",
+            "compaction completed",
+        ]
+    );
+    let created = listener.created.lock();
+    assert_eq!(created[0].column_family, "default");
+    assert_eq!(
+        (&created[0].first_key[..], &created[0].last_key[..]),
+        (&b"b"[..], &b"d"[..])
+    );
+    assert_eq!(
+        (&created[2].first_key[..], &created[2].last_key[..]),
+        (&b"a"[..], &b"d"[..])
+    );
+    assert!(created.iter().all(|table| table.file_size > 0));
+
+    let compactions = listener.compactions.lock();
+    assert_eq!(compactions.len(), 1);
+    let compaction = &compactions[0];
+    assert_eq!(compaction.output_level, 1);
+    let input_ids: Vec<_> = compaction.input_tables.iter().map(|t| t.sst_id).collect();
+    assert_eq!(input_ids, vec![1, 2]);
+    assert_eq!(compaction.output_tables, vec![created[2].clone()]);
+}
+
+#[test]
+fn test_write_stall_events() {
+    let listener = Arc::new(RecordingListener::default());
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 0,
+            level0_slowdown_writes_trigger: 1,
+            write_slowdown_delay: Duration::from_millis(1),
+            event_listeners: vec![listener.clone()],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage.put(b"key_0", b"value").unwrap();
+    storage.sync().unwrap();
+    assert!(listener.stalls.lock().is_empty());
+    storage.put(b"key_1", b"value").unwrap();
+    assert_eq!(
+        *listener.stalls.lock(),
+        vec![WriteStallInfo {
+            cause: WriteStallCause::Level0Files,
+            stopped: false,
+        }]
+    );
+}
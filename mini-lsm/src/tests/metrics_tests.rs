@@ -0,0 +1,61 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::backend::MemoryBackend;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::metrics::{Metrics, Operation};
+
+#[test]
+fn test_operation_latencies() {
+    let metrics = Arc::new(Metrics::new());
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 2,
+            metrics: Some(metrics.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..2 {
+        storage
+            .put(format!("key_{}", idx).as_bytes(), b"value")
+            .unwrap();
+        storage.sync().unwrap();
+    }
+    storage.delete(b"key_0").unwrap();
+    storage.get(b"key_1").unwrap();
+    storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+
+    let count = |operation| metrics.histogram(operation).count();
+    assert_eq!(count(Operation::Put), 2);
+    assert_eq!(count(Operation::Delete), 1);
+    assert_eq!(count(Operation::Get), 1);
+    assert_eq!(count(Operation::Scan), 1);
+    assert_eq!(count(Operation::Sync), 2);
+    assert_eq!(count(Operation::Flush), 2);
+    assert_eq!(count(Operation::Compaction), 1);
+    assert_eq!(count(Operation::Write), 0);
+
+    let out = storage.render_metrics();
+    assert!(out.contains("mini_lsm_level_files{level=\"1\"} 1\n"));
+    assert!(out.contains("# TYPE mini_lsm_block_cache_hits_total counter\n"));
+    assert!(out.contains("mini_lsm_operation_duration_seconds_count{operation=\"flush\"} 2\n"));
+}
+
+#[test]
+fn test_metrics_disabled() {
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    storage.put(b"key", b"value").unwrap();
+    let out = storage.render_metrics();
+    assert!(out.contains("mini_lsm_memtable_bytes 8\n"));
+    assert!(!out.contains("mini_lsm_operation_duration_seconds"));
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::backend::MemoryBackend;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::read_options::{PerfContext, ReadOptions};
+
+fn open_storage() -> LsmStorage {
+    LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 0,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+#[test]
+fn test_multi_get() {
+    let storage = open_storage();
+    // Older versions in the SSTs, overwritten and deleted in newer ones and in the memtable.
+    for idx in 0..1000 {
+        storage.put(&key_of(idx), b"old").unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in (0..1000).step_by(3) {
+        storage.put(&key_of(idx), b"new").unwrap();
+    }
+    for idx in (0..1000).step_by(5) {
+        storage.delete(&key_of(idx)).unwrap();
+    }
+    storage.sync().unwrap();
+    for idx in (0..1000).step_by(7) {
+        storage.put(&key_of(idx), b"latest").unwrap();
+    }
+
+    // Keys out of order, repeated, and missing.
+    let keys = (0..1200)
+        .rev()
+        .step_by(2)
+        .chain([10, 10, 3])
+        .map(key_of)
+        .collect::<Vec<_>>();
+    let keys = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+    let expected = keys
+        .iter()
+        .map(|key| storage.get(key).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(expected[expected.len() - 2], None);
+    assert_eq!(
+        expected[expected.len() - 1],
+        Some(Bytes::from_static(b"new"))
+    );
+    assert_eq!(storage.multi_get(&keys).unwrap(), expected);
+    for threads in [4, 1000] {
+        let options = ReadOptions {
+            multi_get_threads: threads,
+            ..Default::default()
+        };
+        assert_eq!(
+            storage.multi_get_with_options(&keys, &options).unwrap(),
+            expected
+        );
+    }
+    assert!(storage.multi_get(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn test_multi_get_reads_each_block_once() {
+    let storage = open_storage();
+    for keys in [["a", "b", "c"], ["m", "n", "o"]] {
+        for key in keys {
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    storage.put(b"z", b"value").unwrap();
+
+    let perf_context = Arc::new(PerfContext::new());
+    let options = ReadOptions::new().with_perf_context(perf_context.clone());
+    let values = storage
+        .multi_get_with_options(&[b"c", b"z", b"a", b"b", b"x"], &options)
+        .unwrap();
+    let value = Some(Bytes::from_static(b"value"));
+    assert_eq!(
+        values,
+        vec![value.clone(), value.clone(), value.clone(), value, None]
+    );
+    let counters = perf_context.counters();
+    assert_eq!(counters.memtables_probed, 5);
+    assert_eq!(counters.sstables_checked, 2);
+    assert_eq!(counters.blocks_read, 2);
+
+    // Only the older table may hold keys before "m".
+    perf_context.reset();
+    storage
+        .multi_get_with_options(&[b"c", b"a", b"b"], &options)
+        .unwrap();
+    let counters = perf_context.counters();
+    assert_eq!(counters.sstables_skipped, 1);
+    assert_eq!(counters.sstables_checked, 1);
+    assert_eq!(counters.blocks_read + counters.block_cache_hits, 1);
+}
@@ -0,0 +1,132 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::backend::MemoryBackend;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::read_options::{PerfContext, PerfCounters, ReadOptions};
+
+fn open_storage() -> LsmStorage {
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 0,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // Two L0 tables, "a".."c" and "m".."o", and a key in the memtable.
+    for keys in [["a", "b", "c"], ["m", "n", "o"]] {
+        for key in keys {
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.sync().unwrap();
+    }
+    storage.put(b"z", b"value").unwrap();
+    storage
+}
+
+#[test]
+fn test_get_perf_context() {
+    let storage = open_storage();
+    let perf_context = Arc::new(PerfContext::new());
+    let options = ReadOptions::new().with_perf_context(perf_context.clone());
+
+    assert!(storage.get_with_options(b"z", &options).unwrap().is_some());
+    assert_eq!(
+        perf_context.counters(),
+        PerfCounters {
+            memtables_probed: 1,
+            ..Default::default()
+        }
+    );
+
+    // "b" is before the first key of the newer table, so only the older one is read.
+    perf_context.reset();
+    assert!(storage.get_with_options(b"b", &options).unwrap().is_some());
+    let counters = perf_context.counters();
+    assert_eq!(counters.memtables_probed, 1);
+    assert_eq!(counters.sstables_checked, 1);
+    assert_eq!(counters.sstables_skipped, 1);
+    assert_eq!(counters.blocks_read, 1);
+    assert_eq!(counters.block_cache_hits, 0);
+    assert!(counters.block_bytes_read > 0);
+    // The block is stored uncompressed, followed by its codec and checksum.
+    assert_eq!(counters.bytes_decoded + 5, counters.block_bytes_read);
+
+    // The block is cached now.
+    perf_context.reset();
+    assert!(storage.get_with_options(b"b", &options).unwrap().is_some());
+    let counters = perf_context.counters();
+    assert_eq!((counters.blocks_read, counters.block_cache_hits), (0, 1));
+}
+
+#[test]
+fn test_scan_perf_context() {
+    let storage = open_storage();
+    let perf_context = Arc::new(PerfContext::new());
+    let options = ReadOptions::new().with_perf_context(perf_context.clone());
+
+    let mut iter = storage
+        .scan_with_options(Bound::Included(b"b"), Bound::Excluded(b"m"), &options)
+        .unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(iter.key().to_vec());
+        iter.next().unwrap();
+    }
+    assert_eq!(keys, vec![b"b".to_vec(), b"c".to_vec()]);
+    let counters = perf_context.counters();
+    assert_eq!(counters.memtables_probed, 1);
+    assert_eq!(counters.sstables_checked, 1);
+    assert_eq!(counters.sstables_skipped, 1);
+    assert_eq!(counters.blocks_read, 1);
+
+    // Without a perf context, nothing is counted.
+    perf_context.reset();
+    storage.get(b"b").unwrap();
+    assert_eq!(perf_context.counters(), PerfCounters::default());
+}
+
+#[test]
+fn test_perf_context_l1() {
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            target_file_size: 4096,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let key_of = |idx: usize| format!("key_{:04}", idx).into_bytes();
+    for idx in 0..2000 {
+        storage.put(&key_of(idx), b"value").unwrap();
+    }
+    storage.force_full_compaction().unwrap();
+    let l1_files = storage.stats().levels[1].num_files as u64;
+    assert!(l1_files > 4);
+    let perf_context = Arc::new(PerfContext::new());
+    let options = ReadOptions::new().with_perf_context(perf_context.clone());
+
+    // L1 tables do not overlap, so only the one that may hold the key is read.
+    assert!(storage
+        .get_with_options(&key_of(1000), &options)
+        .unwrap()
+        .is_some());
+    let counters = perf_context.counters();
+    assert_eq!(counters.sstables_checked, 1);
+    assert_eq!(counters.sstables_skipped, l1_files - 1);
+    assert_eq!(counters.blocks_read, 1);
+
+    perf_context.reset();
+    let keys = [key_of(0), key_of(1), key_of(1999)];
+    let keys = keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+    let values = storage.multi_get_with_options(&keys, &options).unwrap();
+    assert!(values.iter().all(Option::is_some));
+    let counters = perf_context.counters();
+    assert_eq!(counters.sstables_checked, 2);
+    assert_eq!(counters.sstables_skipped, l1_files - 2);
+    assert_eq!(counters.blocks_read, 2);
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use crate::backend::MemoryBackend;
+use crate::clock::{Clock, ManualClock};
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+
+fn put_and_sync(storage: &LsmStorage, round: usize) {
+    for idx in 0..100 {
+        storage
+            .put(
+                format!("key_{:03}", idx).as_bytes(),
+                format!("value_{}_{}", round, idx).repeat(10).as_bytes(),
+            )
+            .unwrap();
+    }
+    storage.sync().unwrap();
+}
+
+#[test]
+fn test_flush_rate_limit() {
+    let clock = Arc::new(ManualClock::new(0));
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            clock: clock.clone(),
+            level0_compaction_trigger: 0,
+            write_bytes_per_second: 20_000,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // The first flush goes through on the initial burst. The second one waits until all of its
+    // bytes but the last write are paid for.
+    put_and_sync(&storage, 0);
+    assert_eq!(clock.now_millis(), 0);
+    put_and_sync(&storage, 1);
+    let debt = storage.stats().flush_bytes_written - 20_000;
+    let waited = clock.now_millis();
+    assert!(waited > 0 && waited <= debt * 1000 / 20_000, "{}", waited);
+
+    storage.set_write_rate_limit(0);
+    let start = clock.now_millis();
+    put_and_sync(&storage, 2);
+    put_and_sync(&storage, 3);
+    assert_eq!(clock.now_millis(), start);
+    assert_eq!(
+        storage.get(b"key_042").unwrap().unwrap(),
+        "value_3_42".repeat(10).as_bytes()
+    );
+}
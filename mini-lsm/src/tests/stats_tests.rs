@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use crate::backend::MemoryBackend;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions, StorageStats};
+
+fn open_storage() -> LsmStorage {
+    LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            level0_compaction_trigger: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_stats_empty() {
+    let storage = open_storage();
+    let stats = storage.stats();
+    assert_eq!(stats.levels.len(), 1);
+    assert_eq!(stats.levels[0].num_files, 0);
+    assert_eq!(stats.read_amplification, 1);
+    assert_eq!(
+        stats,
+        StorageStats {
+            levels: stats.levels.clone(),
+            read_amplification: 1,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_stats_flush_and_compaction() {
+    let storage = open_storage();
+    storage.put(b"key_1", b"value_1").unwrap();
+    storage.delete(b"key_2").unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.user_bytes_written, 12 + 5);
+    assert_eq!(stats.memtable_bytes, 12 + 5);
+    assert_eq!(stats.flush_bytes_written, 0);
+
+    storage.sync().unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.memtable_bytes, 0);
+    assert_eq!(stats.num_imm_memtables, 0);
+    assert_eq!(stats.levels[0].num_files, 1);
+    assert!(stats.levels[0].size_bytes > 0);
+    assert_eq!(stats.flush_bytes_written, stats.levels[0].size_bytes);
+    assert_eq!(stats.read_amplification, 2);
+    assert!(stats.write_amplification > 1.0);
+
+    // The second flush triggers a compaction of both L0 tables into L1.
+    storage.put(b"key_1", b"value_2").unwrap();
+    storage.sync().unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.levels.len(), 2);
+    assert_eq!(stats.levels[0].num_files, 0);
+    assert_eq!(stats.levels[1].num_files, 1);
+    assert_eq!(stats.compaction_bytes_read, stats.flush_bytes_written);
+    assert_eq!(stats.compaction_bytes_written, stats.levels[1].size_bytes);
+    assert_eq!(stats.read_amplification, 2);
+}
+
+#[test]
+fn test_stats_block_cache() {
+    let storage = open_storage();
+    storage.put(b"key_1", b"value_1").unwrap();
+    storage.sync().unwrap();
+    let stats = storage.stats();
+    assert_eq!((stats.block_cache_hits, stats.block_cache_misses), (0, 0));
+
+    assert_eq!(&storage.get(b"key_1").unwrap().unwrap()[..], b"value_1");
+    assert_eq!(storage.stats().block_cache_misses, 1);
+    assert_eq!(&storage.get(b"key_1").unwrap().unwrap()[..], b"value_1");
+    let stats = storage.stats();
+    assert_eq!((stats.block_cache_hits, stats.block_cache_misses), (1, 1));
+}
+
+#[test]
+fn test_stats_column_families() {
+    let storage = open_storage();
+    storage.create_column_family("other").unwrap();
+    storage.put(b"key_1", b"value_1").unwrap();
+    storage.put_cf("other", b"key_1", b"value_1").unwrap();
+    storage.put_cf("other", b"key_2", b"value_2").unwrap();
+    assert_eq!(storage.stats().memtable_bytes, 3 * 12);
+    storage.sync().unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.levels[0].num_files, 2);
+    assert_eq!(stats.user_bytes_written, 3 * 12);
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use crate::backend::MemoryBackend;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+
+/// Records the name of every span, and the fields recorded after it was created.
+#[derive(Default)]
+struct SpanRecorder {
+    spans: Mutex<Vec<String>>,
+    records: Mutex<Vec<String>>,
+}
+
+struct FieldWriter<'a>(&'a mut String);
+
+impl Visit for FieldWriter<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push_str(&format!(" {}={:?}", field.name(), value));
+    }
+}
+
+impl Subscriber for SpanRecorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut spans = self.spans.lock();
+        spans.push(span.metadata().name().to_string());
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut record = self.spans.lock()[span.into_u64() as usize - 1].clone();
+        values.record(&mut FieldWriter(&mut record));
+        self.records.lock().push(record);
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_read_and_write_spans() {
+    let recorder = Arc::new(SpanRecorder::default());
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        storage.put(b"key", b"value").unwrap();
+        storage.sync().unwrap();
+        storage.get(b"key").unwrap();
+        storage.get(b"key").unwrap();
+    });
+
+    let spans = recorder.spans.lock();
+    for name in [
+        "put",
+        "sync",
+        "flush",
+        "get",
+        "search_sstables",
+        "read_block",
+    ] {
+        assert!(spans.iter().any(|span| span == name), "no {} span", name);
+    }
+    assert_eq!(
+        *recorder.records.lock(),
+        vec![
+            "read_block bytes=22",
+            "read_block_cached cache_hit=false",
+            "read_block_cached cache_hit=true",
+        ]
+    );
+}
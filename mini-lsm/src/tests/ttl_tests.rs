@@ -0,0 +1,161 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::clock::ManualClock;
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions};
+use crate::table::{FileObject, SsTable, SsTableIterator};
+use crate::write_batch::WriteBatch;
+
+const DIR: &str = "/lsm";
+
+fn open_storage(backend: &Arc<MemoryBackend>, clock: &Arc<ManualClock>) -> LsmStorage {
+    LsmStorage::open_with_options(
+        DIR,
+        LsmStorageOptions {
+            backend: backend.clone(),
+            clock: clock.clone(),
+            level0_compaction_trigger: 2,
+            min_blob_size: 1024,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
+
+fn scan_keys(storage: &LsmStorage) -> Vec<Vec<u8>> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(iter.key().to_vec());
+        iter.next().unwrap();
+    }
+    keys
+}
+
+#[test]
+fn test_ttl_in_memtable() {
+    let backend = Arc::new(MemoryBackend::new());
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let storage = open_storage(&backend, &clock);
+    storage.put(b"a", b"1").unwrap();
+    storage
+        .put_with_ttl(b"b", b"2", Duration::from_secs(10))
+        .unwrap();
+    storage.put(b"c", b"3").unwrap();
+    assert_eq!(&storage.get(b"b").unwrap().unwrap()[..], b"2");
+    assert_eq!(scan_keys(&storage), vec![b"a", b"b", b"c"]);
+
+    clock.advance(Duration::from_secs(10));
+    assert!(storage.get(b"b").unwrap().is_none());
+    assert_eq!(scan_keys(&storage), vec![b"a", b"c"]);
+
+    // Writing the key again makes it visible with the new value.
+    storage.put(b"b", b"22").unwrap();
+    assert_eq!(&storage.get(b"b").unwrap().unwrap()[..], b"22");
+}
+
+#[test]
+fn test_ttl_in_sst_hides_older_versions() {
+    let backend = Arc::new(MemoryBackend::new());
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let storage = open_storage(&backend, &clock);
+    storage.put(b"session", b"old").unwrap();
+    storage.put(b"user", b"alice").unwrap();
+    storage.sync().unwrap();
+    storage
+        .put_with_ttl(b"session", b"new", Duration::from_secs(30))
+        .unwrap();
+    let large = b"x".repeat(4096);
+    storage
+        .put_with_ttl(b"token", &large, Duration::from_secs(60))
+        .unwrap();
+    storage.sync().unwrap();
+
+    clock.advance(Duration::from_secs(29));
+    assert_eq!(&storage.get(b"session").unwrap().unwrap()[..], b"new");
+    assert_eq!(storage.get(b"token").unwrap().unwrap(), large);
+    assert_eq!(
+        scan_keys(&storage),
+        vec![&b"session"[..], b"token", b"user"]
+    );
+
+    clock.advance(Duration::from_secs(1));
+    assert!(storage.get(b"session").unwrap().is_none());
+    assert_eq!(storage.get(b"token").unwrap().unwrap(), large);
+    assert_eq!(scan_keys(&storage), vec![&b"token"[..], b"user"]);
+
+    clock.advance(Duration::from_secs(30));
+    assert!(storage.get(b"token").unwrap().is_none());
+    assert_eq!(scan_keys(&storage), vec![b"user"]);
+}
+
+#[test]
+fn test_compaction_drops_expired_entries() {
+    let backend = Arc::new(MemoryBackend::new());
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let storage = open_storage(&backend, &clock);
+    for idx in 0..10 {
+        let key = format!("key_{}", idx);
+        storage
+            .put_with_ttl(key.as_bytes(), b"expiring", Duration::from_secs(idx))
+            .unwrap();
+    }
+    storage.put(b"key_9", b"kept").unwrap();
+    storage.sync().unwrap();
+    clock.advance(Duration::from_secs(5));
+    storage.put(b"other", b"value").unwrap();
+    storage.sync().unwrap();
+
+    // The two L0 tables were compacted into one L1 table without the expired keys. `key_5` expires
+    // exactly now.
+    let mut ssts = backend.list(Path::new(DIR)).unwrap();
+    ssts.retain(|path| path.extension().and_then(|ext| ext.to_str()) == Some("sst"));
+    assert_eq!(ssts.len(), 1);
+    let file = FileObject::open_with_backend(backend.as_ref(), &ssts[0]).unwrap();
+    let table = Arc::new(SsTable::open(0, None, file).unwrap());
+    let mut iter = SsTableIterator::create_and_seek_to_first(table).unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((iter.key().to_vec(), iter.meta().expires_at));
+        iter.next().unwrap();
+    }
+    assert_eq!(
+        entries,
+        vec![
+            (b"key_6".to_vec(), Some(1_006_000)),
+            (b"key_7".to_vec(), Some(1_007_000)),
+            (b"key_8".to_vec(), Some(1_008_000)),
+            (b"key_9".to_vec(), None),
+            (b"other".to_vec(), None),
+        ]
+    );
+}
+
+#[test]
+fn test_key_too_large() {
+    let backend = Arc::new(MemoryBackend::new());
+    let clock = Arc::new(ManualClock::new(1_000_000));
+    let storage = open_storage(&backend, &clock);
+    let key = vec![b'k'; 65536];
+    assert!(storage.put(&key, b"1").is_err());
+    assert!(storage
+        .put_with_ttl(&key, b"1", Duration::from_secs(10))
+        .is_err());
+    assert!(storage.delete(&key).is_err());
+    let mut batch = WriteBatch::new();
+    batch.put(b"a", b"1").delete(&key);
+    assert!(storage.write(&batch).is_err());
+    assert!(storage.get(b"a").unwrap().is_none());
+
+    // Expiring entries keep the full key length.
+    let key = vec![b'k'; 65535];
+    storage
+        .put_with_ttl(&key, b"1", Duration::from_secs(10))
+        .unwrap();
+    storage.sync().unwrap();
+    assert_eq!(&storage.get(&key).unwrap().unwrap()[..], b"1");
+}
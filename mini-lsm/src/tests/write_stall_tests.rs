@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backend::fault::FaultInjectionBackend;
+use crate::backend::MemoryBackend;
+use crate::clock::ManualClock;
+use crate::lsm_storage::{LsmStorage, LsmStorageOptions, WriteStallStats};
+
+#[test]
+fn test_write_slowdown_on_level0_files() {
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            clock: Arc::new(ManualClock::new(0)),
+            level0_compaction_trigger: 0,
+            level0_slowdown_writes_trigger: 2,
+            write_slowdown_delay: Duration::from_millis(5),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..2 {
+        storage
+            .put(format!("key_{}", idx).as_bytes(), b"value")
+            .unwrap();
+        storage.sync().unwrap();
+    }
+    assert_eq!(storage.write_stall_stats(), WriteStallStats::default());
+
+    storage.put(b"key_2", b"value").unwrap();
+    storage.delete(b"key_0").unwrap();
+    let stats = storage.write_stall_stats();
+    assert_eq!(stats.slowdowns, 2);
+    assert_eq!(stats.stops, 0);
+    assert_eq!(stats.stall_duration, Duration::from_millis(10));
+}
+
+#[test]
+fn test_write_stop_on_imm_memtables() {
+    let backend = FaultInjectionBackend::new(0);
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(backend.clone()),
+            max_imm_memtables: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // Every failed sync leaves one more immutable memtable behind.
+    backend.set_fail_sync(true);
+    for idx in 0..2 {
+        storage
+            .put(format!("key_{}", idx).as_bytes(), b"value")
+            .unwrap();
+        assert!(storage.sync().is_err());
+    }
+
+    // The stopped writer flushes the immutable memtables itself, and fails if it cannot.
+    assert!(storage.put(b"key_2", b"value").is_err());
+    assert_eq!(storage.stats().num_imm_memtables, 2);
+    assert!(storage.get(b"key_2").unwrap().is_none());
+
+    backend.set_fail_sync(false);
+    storage.put(b"key_2", b"value").unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.num_imm_memtables, 0);
+    assert_eq!(stats.levels[0].num_files, 2);
+    for idx in 0..3 {
+        let key = format!("key_{}", idx);
+        assert_eq!(&storage.get(key.as_bytes()).unwrap().unwrap()[..], b"value");
+    }
+    assert_eq!(stats.write_stall.stops, 2);
+}
+
+#[test]
+fn test_write_stop_on_level0_files() {
+    let storage = LsmStorage::open_with_options(
+        "/lsm",
+        LsmStorageOptions {
+            backend: Arc::new(MemoryBackend::new()),
+            clock: Arc::new(ManualClock::new(0)),
+            level0_compaction_trigger: 0,
+            level0_stop_writes_trigger: 2,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    for idx in 0..2 {
+        storage
+            .put(format!("key_{}", idx).as_bytes(), b"value")
+            .unwrap();
+        storage.sync().unwrap();
+    }
+
+    // The stopped writer compacts L0 itself.
+    storage.delete(b"key_0").unwrap();
+    let stats = storage.stats();
+    assert_eq!(stats.levels[0].num_files, 0);
+    assert_eq!(stats.levels[1].num_files, 1);
+    assert!(storage.get(b"key_0").unwrap().is_none());
+    assert_eq!(&storage.get(b"key_1").unwrap().unwrap()[..], b"value");
+    assert_eq!(stats.write_stall.stops, 1);
+}
@@ -0,0 +1,25 @@
+//! Spans and fields for `tracing`, compiled out unless the `tracing` feature is enabled.
+
+/// Enter a debug-level span, which is exited at the end of the enclosing scope. Name it with
+/// `enter_span!(span = "name", ...)` to record fields into it later.
+macro_rules! enter_span {
+    ($span:ident = $name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let $span = tracing::debug_span!($name $(, $($fields)*)?).entered();
+    };
+    ($name:literal $(, $($fields:tt)*)?) => {
+        $crate::trace::enter_span!(_span = $name $(, $($fields)*)?);
+    };
+}
+
+/// Record the value of a field declared as `tracing::field::Empty` by a named span.
+macro_rules! record_field {
+    ($span:ident, $field:ident, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        $span.record(stringify!($field), $value);
+        #[cfg(not(feature = "tracing"))]
+        let _ = $value;
+    };
+}
+
+pub(crate) use {enter_span, record_field};
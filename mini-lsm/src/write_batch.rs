@@ -0,0 +1,69 @@
+use bytes::Bytes;
+
+use crate::lsm_storage::DEFAULT_COLUMN_FAMILY;
+
+/// A single update of a write batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum WriteOp {
+    Put {
+        column_family: String,
+        key: Bytes,
+        value: Bytes,
+    },
+    Delete {
+        column_family: String,
+        key: Bytes,
+    },
+}
+
+/// Updates to one or more column families, applied together by
+/// [`LsmStorage::write`](crate::lsm_storage::LsmStorage::write).
+#[derive(Clone, Debug, Default)]
+pub struct WriteBatch {
+    pub(crate) ops: Vec<WriteOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put a key-value pair into the default column family.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> &mut Self {
+        self.put_cf(DEFAULT_COLUMN_FAMILY, key, value)
+    }
+
+    /// Put a key-value pair into the given column family.
+    pub fn put_cf(&mut self, column_family: &str, key: &[u8], value: &[u8]) -> &mut Self {
+        self.ops.push(WriteOp::Put {
+            column_family: column_family.to_string(),
+            key: Bytes::copy_from_slice(key),
+            value: Bytes::copy_from_slice(value),
+        });
+        self
+    }
+
+    /// Remove a key from the default column family.
+    pub fn delete(&mut self, key: &[u8]) -> &mut Self {
+        self.delete_cf(DEFAULT_COLUMN_FAMILY, key)
+    }
+
+    /// Remove a key from the given column family.
+    pub fn delete_cf(&mut self, column_family: &str, key: &[u8]) -> &mut Self {
+        self.ops.push(WriteOp::Delete {
+            column_family: column_family.to_string(),
+            key: Bytes::copy_from_slice(key),
+        });
+        self
+    }
+
+    /// Get the number of updates in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Check if the batch has no update.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}